@@ -1,46 +1,231 @@
 use codec::Encode;
-use parking_lot::RwLock;
+use log::debug;
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Serialize;
 use sp_core::H256;
 use parity_secretstore_primitives::{
 	Address, ServerKeyId,
 	acl_storage::AclStorage,
 	error::Error,
 };
-use crate::substrate_client::Client;
+use crate::{
+	async_bridge::AsyncBridge,
+	substrate_client::Client,
+};
+
+/// Max number of ACL decisions that are cached.
+const ACL_CACHE_SIZE: usize = 1024;
+
+/// ACL cache statistics, reported by admin endpoint.
+#[derive(Debug, Serialize)]
+pub struct AclCacheStatus {
+	/// Number of checks that have been answered from the cache.
+	pub hits: u64,
+	/// Number of checks that have required runtime call.
+	pub misses: u64,
+	/// Number of decisions that are currently cached.
+	pub cached_decisions: usize,
+}
 
 pub struct OnChainAclStorage {
 	client: Client,
-	data: RwLock<OnChainAclStorageData>,
+	bridge: AsyncBridge,
+	data: Mutex<OnChainAclStorageData>,
 }
 
 struct OnChainAclStorageData {
 	best_block: Option<(u32, H256)>,
+	/// ACL decisions at the best block: (block hash, server key id, requester) => decision.
+	cache: LruCache<(H256, ServerKeyId, Address), bool>,
+	/// Number of checks that have been answered from the cache.
+	cache_hits: u64,
+	/// Number of checks that have required runtime call.
+	cache_misses: u64,
 }
 
 impl OnChainAclStorage {
-	pub fn new(client: Client) -> Self {
+	pub fn new(client: Client, bridge: AsyncBridge) -> Self {
 		OnChainAclStorage {
 			client,
-			data: RwLock::new(OnChainAclStorageData {
-				best_block: None,
-			}),
+			bridge,
+			data: Mutex::new(OnChainAclStorageData::new(ACL_CACHE_SIZE)),
 		}
 	}
 
 	pub fn set_best_block(&self, best_block: (u32, H256)) {
-		self.data.write().best_block = Some(best_block);
+		let mut data = self.data.lock();
+		if data.set_best_block(best_block) {
+			debug!(
+				target: "secretstore",
+				"ACL cache has been invalidated at block {}. Hits: {}, misses: {}",
+				best_block.0,
+				data.cache_hits,
+				data.cache_misses,
+			);
+		}
+	}
+
+	/// Get ACL cache statistics.
+	pub fn cache_status(&self) -> AclCacheStatus {
+		self.data.lock().cache_status()
 	}
 }
 
 impl AclStorage for OnChainAclStorage {
 	fn check(&self, requester_address: Address, server_key_id: &ServerKeyId) -> Result<bool, Error> {
-		let best_block = self.data.read().best_block.ok_or_else(|| Error::Internal("disconnected".into()))?;
-		futures::executor::block_on(async {
-			self.client.call_runtime_method(
+		let best_block = {
+			let mut data = self.data.lock();
+			let best_block = data.best_block.ok_or_else(|| Error::Internal("disconnected".into()))?;
+			if let Some(decision) = data.cached_decision(server_key_id, &requester_address) {
+				return Ok(decision);
+			}
+			best_block
+		};
+
+		let client = self.client.clone();
+		let arguments = vec![server_key_id.encode(), requester_address.encode()];
+		let decision = self.bridge.run(async move {
+			client.call_runtime_method(
 				best_block.1,
 				"SecretStoreAclApi_check",
-				vec![server_key_id.encode(), requester_address.encode()],
-			).await.map_err(|err| Error::Internal(format!("{:?}", err)))
-		})
+				arguments,
+			).await
+		}).map_err(|err| Error::Internal(format!("{:?}", err)))?;
+
+		self.data.lock().cache_decision(best_block.1, *server_key_id, requester_address, decision);
+
+		Ok(decision)
+	}
+}
+
+impl OnChainAclStorageData {
+	/// Create empty data with given cache size.
+	fn new(cache_size: usize) -> Self {
+		OnChainAclStorageData {
+			best_block: None,
+			cache: LruCache::new(cache_size),
+			cache_hits: 0,
+			cache_misses: 0,
+		}
+	}
+
+	/// Update best block. Returns true if cache has been invalidated.
+	fn set_best_block(&mut self, best_block: (u32, H256)) -> bool {
+		let is_best_block_changed = self.best_block.map(|(_, hash)| hash) != Some(best_block.1);
+		if is_best_block_changed {
+			self.cache.clear();
+		}
+		self.best_block = Some(best_block);
+		is_best_block_changed
+	}
+
+	/// Get cached decision, made at the best block.
+	fn cached_decision(&mut self, server_key_id: &ServerKeyId, requester_address: &Address) -> Option<bool> {
+		let best_block_hash = self.best_block?.1;
+		match self.cache.get(&(best_block_hash, *server_key_id, *requester_address)) {
+			Some(decision) => {
+				self.cache_hits += 1;
+				Some(*decision)
+			},
+			None => {
+				self.cache_misses += 1;
+				None
+			},
+		}
+	}
+
+	/// Cache decision that has been made at given block. Decision is dropped if best block
+	/// has been changed while we were waiting for the decision.
+	fn cache_decision(&mut self, block_hash: H256, server_key_id: ServerKeyId, requester_address: Address, decision: bool) {
+		if self.best_block.map(|(_, hash)| hash) == Some(block_hash) {
+			self.cache.put((block_hash, server_key_id, requester_address), decision);
+		}
+	}
+
+	/// Get ACL cache statistics.
+	fn cache_status(&self) -> AclCacheStatus {
+		AclCacheStatus {
+			hits: self.cache_hits,
+			misses: self.cache_misses,
+			cached_decisions: self.cache.len(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(index: u8) -> (ServerKeyId, Address) {
+		([index; 32].into(), [index; 20].into())
+	}
+
+	fn data_at_block(cache_size: usize, block: u8) -> OnChainAclStorageData {
+		let mut data = OnChainAclStorageData::new(cache_size);
+		data.set_best_block((block as u32, [block; 32].into()));
+		data
+	}
+
+	fn cache_decision(data: &mut OnChainAclStorageData, index: u8, decision: bool) {
+		let block_hash = data.best_block.unwrap().1;
+		let (server_key_id, requester_address) = key(index);
+		data.cache_decision(block_hash, server_key_id, requester_address, decision);
+	}
+
+	fn cached_decision(data: &mut OnChainAclStorageData, index: u8) -> Option<bool> {
+		let (server_key_id, requester_address) = key(index);
+		data.cached_decision(&server_key_id, &requester_address)
+	}
+
+	#[test]
+	fn cache_hits_and_misses_are_counted() {
+		let mut data = data_at_block(4, 1);
+		assert_eq!(cached_decision(&mut data, 1), None);
+
+		cache_decision(&mut data, 1, true);
+		cache_decision(&mut data, 2, false);
+		assert_eq!(cached_decision(&mut data, 1), Some(true));
+		assert_eq!(cached_decision(&mut data, 2), Some(false));
+
+		let status = data.cache_status();
+		assert_eq!((status.hits, status.misses, status.cached_decisions), (2, 1, 2));
+	}
+
+	#[test]
+	fn least_recently_used_decision_is_evicted() {
+		let mut data = data_at_block(2, 1);
+		cache_decision(&mut data, 1, true);
+		cache_decision(&mut data, 2, true);
+
+		// check refreshes recency of the first decision => second decision is evicted
+		assert_eq!(cached_decision(&mut data, 1), Some(true));
+		cache_decision(&mut data, 3, true);
+
+		assert_eq!(cached_decision(&mut data, 1), Some(true));
+		assert_eq!(cached_decision(&mut data, 2), None);
+		assert_eq!(cached_decision(&mut data, 3), Some(true));
+	}
+
+	#[test]
+	fn cache_is_invalidated_when_best_block_changes() {
+		let mut data = data_at_block(4, 1);
+		cache_decision(&mut data, 1, true);
+
+		assert!(!data.set_best_block((1, [1; 32].into())));
+		assert_eq!(cached_decision(&mut data, 1), Some(true));
+
+		assert!(data.set_best_block((2, [2; 32].into())));
+		assert_eq!(cached_decision(&mut data, 1), None);
+		assert_eq!(data.cache_status().cached_decisions, 0);
+	}
+
+	#[test]
+	fn decision_made_at_previous_best_block_is_not_cached() {
+		let mut data = data_at_block(4, 2);
+		let (server_key_id, requester_address) = key(1);
+		data.cache_decision([1; 32].into(), server_key_id, requester_address, true);
+
+		assert_eq!(cached_decision(&mut data, 1), None);
 	}
 }
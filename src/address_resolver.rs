@@ -0,0 +1,230 @@
+use std::{
+	collections::{HashMap, HashSet},
+	net::{IpAddr, SocketAddr, ToSocketAddrs},
+	time::{Duration, Instant},
+};
+use log::warn;
+use parking_lot::RwLock;
+
+/// Interval after which resolved address is resolved again.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// All possible errors that can occur when resolving on-chain network address.
+#[derive(Debug)]
+pub enum Error {
+	/// Address record is not a valid UTF-8 string.
+	InvalidEncoding,
+	/// Address record has invalid format.
+	InvalidFormat(String),
+	/// Failed to resolve host name.
+	Resolve(String, std::io::Error),
+	/// Host name has been resolved to empty list of addresses.
+	NoAddresses(String),
+}
+
+/// Network address, registered on chain.
+#[derive(Debug, Clone, PartialEq)]
+enum NetworkAddress {
+	/// IP address and port.
+	Ip(SocketAddr),
+	/// Host name and port.
+	Host(String, u16),
+}
+
+/// Resolved address, cached by resolver.
+#[derive(Clone, Copy)]
+struct CachedAddress {
+	/// Resolved address.
+	address: SocketAddr,
+	/// Time when address has been resolved.
+	resolved_at: Instant,
+}
+
+/// Resolver of network addresses that are registered on chain. Addresses may be either
+/// `host:port` strings, or multiaddrs (`/ip4/127.0.0.1/tcp/10000`, `/dns4/example.com/tcp/10000`).
+#[derive(Default)]
+pub struct AddressResolver {
+	/// Resolved addresses, by on-chain record.
+	cache: RwLock<HashMap<Vec<u8>, CachedAddress>>,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match *self {
+			Error::InvalidEncoding => write!(f, "address is not a valid UTF-8 string"),
+			Error::InvalidFormat(ref address) => write!(f, "invalid address {}", address),
+			Error::Resolve(ref host, ref error) => write!(f, "failed to resolve {}: {}", host, error),
+			Error::NoAddresses(ref host) => write!(f, "{} has been resolved to empty list of addresses", host),
+		}
+	}
+}
+
+impl AddressResolver {
+	/// Resolve on-chain address record. Addresses are cached and resolved again after
+	/// `RESOLVE_INTERVAL`. If resolution fails, previously resolved address is returned.
+	pub async fn resolve(&self, record: &[u8]) -> Result<SocketAddr, Error> {
+		let cached_address = self.cache.read().get(record).cloned();
+		if let Some(cached_address) = cached_address {
+			if cached_address.resolved_at.elapsed() < RESOLVE_INTERVAL {
+				return Ok(cached_address.address);
+			}
+		}
+
+		let resolve_result = match parse_address(record)? {
+			NetworkAddress::Ip(address) => Ok(address),
+			NetworkAddress::Host(host, port) => resolve_host(host, port).await,
+		};
+
+		match resolve_result {
+			Ok(address) => {
+				self.cache.write().insert(record.to_vec(), CachedAddress {
+					address,
+					resolved_at: Instant::now(),
+				});
+				Ok(address)
+			},
+			Err(error) => match cached_address {
+				Some(cached_address) => {
+					warn!(
+						target: "secretstore_net",
+						"Failed to re-resolve address: {}. Using previously resolved address {}",
+						error,
+						cached_address.address,
+					);
+
+					Ok(cached_address.address)
+				},
+				None => Err(error),
+			},
+		}
+	}
+
+	/// Forget resolved addresses of all records, except given records.
+	pub fn retain<'a>(&self, records: impl Iterator<Item = &'a [u8]>) {
+		let records = records.collect::<HashSet<_>>();
+		self.cache.write().retain(|record, _| records.contains(record.as_slice()));
+	}
+}
+
+/// Parse on-chain address record.
+fn parse_address(record: &[u8]) -> Result<NetworkAddress, Error> {
+	let address = std::str::from_utf8(record).map_err(|_| Error::InvalidEncoding)?;
+	let invalid_format = || Error::InvalidFormat(address.into());
+	if address.starts_with('/') {
+		let parts = address[1..].split('/').collect::<Vec<_>>();
+		return match parts.as_slice() {
+			[protocol, host, "tcp", port] => {
+				let port = port.parse().map_err(|_| invalid_format())?;
+				match *protocol {
+					"ip4" | "ip6" => host.parse::<IpAddr>()
+						.map(|ip| NetworkAddress::Ip(SocketAddr::new(ip, port)))
+						.map_err(|_| invalid_format()),
+					"dns" | "dns4" | "dns6" => Ok(NetworkAddress::Host((*host).into(), port)),
+					_ => Err(invalid_format()),
+				}
+			},
+			_ => Err(invalid_format()),
+		};
+	}
+
+	if let Ok(address) = address.parse() {
+		return Ok(NetworkAddress::Ip(address));
+	}
+
+	let port_separator = address.rfind(':').ok_or_else(invalid_format)?;
+	let host = &address[..port_separator];
+	let port = address[port_separator + 1..].parse().map_err(|_| invalid_format())?;
+	if host.is_empty() {
+		return Err(invalid_format());
+	}
+
+	Ok(NetworkAddress::Host(host.into(), port))
+}
+
+/// Resolve host name on background thread, because std resolver is blocking.
+async fn resolve_host(host: String, port: u16) -> Result<SocketAddr, Error> {
+	let (result_sender, result_receiver) = futures::channel::oneshot::channel();
+	let thread_host = host.clone();
+	let spawn_result = std::thread::Builder::new()
+		.name("address-resolver".into())
+		.spawn(move || {
+			let result = (thread_host.as_str(), port).to_socket_addrs()
+				.map_err(|error| Error::Resolve(thread_host.clone(), error))
+				.and_then(|mut addresses| addresses.next().ok_or_else(|| Error::NoAddresses(thread_host.clone())));
+			let _ = result_sender.send(result);
+		});
+
+	if let Err(error) = spawn_result {
+		return Err(Error::Resolve(host, error));
+	}
+
+	result_receiver
+		.await
+		.unwrap_or_else(|_| Err(Error::NoAddresses(host)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(address: &str) -> Result<NetworkAddress, Error> {
+		parse_address(address.as_bytes())
+	}
+
+	#[test]
+	fn multiaddr_is_parsed() {
+		assert_eq!(
+			parse("/ip4/127.0.0.1/tcp/10000").unwrap(),
+			NetworkAddress::Ip("127.0.0.1:10000".parse().unwrap()),
+		);
+		assert_eq!(
+			parse("/ip6/::1/tcp/10000").unwrap(),
+			NetworkAddress::Ip("[::1]:10000".parse().unwrap()),
+		);
+		assert_eq!(
+			parse("/dns4/example.com/tcp/10000").unwrap(),
+			NetworkAddress::Host("example.com".into(), 10000),
+		);
+	}
+
+	#[test]
+	fn host_and_port_are_parsed() {
+		assert_eq!(
+			parse("127.0.0.1:10000").unwrap(),
+			NetworkAddress::Ip("127.0.0.1:10000".parse().unwrap()),
+		);
+		assert_eq!(
+			parse("[::1]:10000").unwrap(),
+			NetworkAddress::Ip("[::1]:10000".parse().unwrap()),
+		);
+		assert_eq!(
+			parse("example.com:10000").unwrap(),
+			NetworkAddress::Host("example.com".into(), 10000),
+		);
+	}
+
+	#[test]
+	fn invalid_address_is_rejected() {
+		for address in &[
+			"",
+			"example.com",
+			":10000",
+			"example.com:port",
+			"example.com:100000",
+			"/ip4/127.0.0.1/udp/10000",
+			"/ip4/example.com/tcp/10000",
+			"/ip4/127.0.0.1/tcp",
+			"/onion/example/tcp/10000",
+		] {
+			match parse(address) {
+				Err(Error::InvalidFormat(_)) => (),
+				result => panic!("unexpected result for {}: {:?}", address, result),
+			}
+		}
+
+		match parse_address(&[0xFF, 0xFE]) {
+			Err(Error::InvalidEncoding) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+}
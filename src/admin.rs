@@ -0,0 +1,166 @@
+use std::{
+	io::{BufRead, BufReader, Write},
+	net::{TcpListener, TcpStream},
+	sync::Arc,
+	time::Duration,
+};
+use log::{error, info, warn};
+use serde::Deserialize;
+use parity_secretstore_primitives::key_server_set::MigrationId;
+use crate::{
+	acl_storage::OnChainAclStorage,
+	configuration::AdminConfiguration,
+	key_server_set::OnChainKeyServerSet,
+};
+
+/// Admin connection is closed if no request is received during this interval.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Admin endpoint request.
+///
+/// Requests and responses are newline-delimited JSON objects, i.e.
+/// `{"method":"abstain","migration_id":"0x..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+enum Request {
+	/// Get current migration status.
+	MigrationStatus,
+	/// Get ACL cache statistics.
+	AclCacheStatus,
+	/// Resubmit migration transactions on next attempt.
+	ForceRetry,
+	/// Stop submitting transactions for given migration.
+	Abstain {
+		/// Hex-encoded migration ID.
+		migration_id: String,
+	},
+	/// Resume submitting migration transactions.
+	Resume,
+}
+
+/// Start admin endpoint (if enabled).
+pub fn start(
+	config: AdminConfiguration,
+	key_server_set: Arc<OnChainKeyServerSet>,
+	acl_storage: Arc<OnChainAclStorage>,
+) -> Result<(), std::io::Error> {
+	let listen_address = match config.listen_address {
+		Some(listen_address) => listen_address,
+		None => return Ok(()),
+	};
+
+	let listener = TcpListener::bind(&listen_address)?;
+	info!(
+		target: "secretstore",
+		"Admin endpoint is listening on {}",
+		listen_address,
+	);
+
+	std::thread::Builder::new()
+		.name("admin-endpoint".into())
+		.spawn(move || {
+			for stream in listener.incoming() {
+				let stream = match stream {
+					Ok(stream) => stream,
+					Err(error) => {
+						warn!(
+							target: "secretstore",
+							"Failed to accept admin connection: {}",
+							error,
+						);
+
+						continue;
+					},
+				};
+
+				let key_server_set = key_server_set.clone();
+				let acl_storage = acl_storage.clone();
+				let spawn_result = std::thread::Builder::new()
+					.name("admin-connection".into())
+					.spawn(move || if let Err(error) = serve_connection(stream, &key_server_set, &acl_storage) {
+						warn!(
+							target: "secretstore",
+							"Admin connection has failed: {}",
+							error,
+						);
+					});
+				if let Err(error) = spawn_result {
+					warn!(
+						target: "secretstore",
+						"Failed to spawn admin connection thread: {}",
+						error,
+					);
+				}
+			}
+
+			error!(
+				target: "secretstore",
+				"Admin endpoint has stopped",
+			);
+		})
+		.map(|_| ())
+}
+
+/// Serve requests of single admin connection.
+fn serve_connection(
+	stream: TcpStream,
+	key_server_set: &OnChainKeyServerSet,
+	acl_storage: &OnChainAclStorage,
+) -> Result<(), std::io::Error> {
+	stream.set_read_timeout(Some(READ_TIMEOUT))?;
+	let mut writer = stream.try_clone()?;
+	for line in BufReader::new(stream).lines() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let response = match serde_json::from_str::<Request>(&line) {
+			Ok(request) => process_request(request, key_server_set, acl_storage),
+			Err(error) => Err(format!("invalid request: {}", error)),
+		};
+		let response = match response {
+			Ok(result) => serde_json::json!({ "result": result }),
+			Err(error) => serde_json::json!({ "error": error }),
+		};
+
+		writeln!(writer, "{}", response)?;
+	}
+
+	Ok(())
+}
+
+/// Process single admin request.
+fn process_request(
+	request: Request,
+	key_server_set: &OnChainKeyServerSet,
+	acl_storage: &OnChainAclStorage,
+) -> Result<serde_json::Value, String> {
+	info!(
+		target: "secretstore",
+		"Processing admin request: {:?}",
+		request,
+	);
+
+	match request {
+		Request::MigrationStatus => serde_json::to_value(key_server_set.migration_status())
+			.map_err(|error| error.to_string()),
+		Request::AclCacheStatus => serde_json::to_value(acl_storage.cache_status())
+			.map_err(|error| error.to_string()),
+		Request::ForceRetry => {
+			key_server_set.force_retry();
+			Ok(serde_json::Value::Null)
+		},
+		Request::Abstain { migration_id } => {
+			let migration_id = migration_id.trim_start_matches("0x")
+				.parse::<MigrationId>()
+				.map_err(|error| format!("invalid migration id: {:?}", error))?;
+			key_server_set.abstain(migration_id);
+			Ok(serde_json::Value::Null)
+		},
+		Request::Resume => {
+			key_server_set.resume();
+			Ok(serde_json::Value::Null)
+		},
+	}
+}
@@ -0,0 +1,77 @@
+use std::future::Future;
+use futures::executor::ThreadPool;
+
+/// Number of worker threads that are running bridged futures.
+const WORKER_THREADS: usize = 4;
+
+/// Bridge that allows synchronous code (i.e. implementations of SecretStore traits) to
+/// run futures without blocking async executor threads.
+///
+/// Futures are spawned on the dedicated worker thread pool and the results are sent back
+/// to the caller over the channel. So the caller thread is only blocked on the channel and
+/// it never tries to drive futures itself. Tokio workers that are blocked on the channel
+/// are handed over to `tokio_threadpool::blocking`.
+#[derive(Clone)]
+pub struct AsyncBridge {
+	/// Worker thread pool.
+	pool: ThreadPool,
+}
+
+impl AsyncBridge {
+	/// Create new bridge.
+	pub fn new() -> Result<Self, std::io::Error> {
+		Ok(AsyncBridge {
+			pool: ThreadPool::builder()
+				.name_prefix("async-bridge-")
+				.pool_size(WORKER_THREADS)
+				.create()?,
+		})
+	}
+
+	/// Run future on the worker thread pool and wait for its result.
+	///
+	/// When called from the tokio thread pool worker, the worker is marked as blocking while
+	/// waiting, so that other tasks are moved to other workers.
+	pub fn run<F, T, E>(&self, future: F) -> Result<T, E>
+		where
+			F: Future<Output = Result<T, E>> + Send + 'static,
+			T: Send + 'static,
+			E: From<Canceled> + Send + 'static,
+	{
+		let (result_sender, result_receiver) = std::sync::mpsc::sync_channel(1);
+		self.pool.spawn_ok(async move {
+			let _ = result_sender.send(future.await);
+		});
+
+		let mut result_receiver = Some(result_receiver);
+		let result = match tokio_threadpool::blocking(|| wait_result(result_receiver.take())) {
+			Ok(futures01::Async::Ready(result)) => result,
+			// either we are not on the tokio worker thread, or there are no spare threads
+			// for blocking operations => the only option is to block current thread
+			Ok(futures01::Async::NotReady) | Err(_) => wait_result(result_receiver.take()),
+		};
+		result?
+	}
+
+	/// Run future on the worker thread pool without waiting for its result.
+	pub fn spawn<F>(&self, future: F)
+		where
+			F: Future<Output = ()> + Send + 'static,
+	{
+		self.pool.spawn_ok(future);
+	}
+}
+
+/// Bridged future has been dropped before completion (i.e. it has panicked).
+#[derive(Debug)]
+pub struct Canceled;
+
+/// Wait for result of bridged future.
+fn wait_result<T>(
+	result_receiver: Option<std::sync::mpsc::Receiver<T>>,
+) -> Result<T, Canceled> {
+	result_receiver
+		.ok_or(Canceled)?
+		.recv()
+		.map_err(|_| Canceled)
+}
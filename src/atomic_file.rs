@@ -0,0 +1,44 @@
+use std::{
+	io::Write,
+	path::Path,
+};
+
+/// Replace file contents atomically. Contents are written to the temporary file, which is
+/// synced to disk and renamed then. Parent directory is synced after rename, so that the
+/// new file survives power loss.
+pub fn write(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+	let temp_path = path.with_extension("tmp");
+	{
+		let mut temp_file = std::fs::File::create(&temp_path)?;
+		temp_file.write_all(contents)?;
+		temp_file.sync_all()?;
+	}
+
+	std::fs::rename(&temp_path, path)?;
+
+	let directory = match path.parent() {
+		Some(directory) if !directory.as_os_str().is_empty() => directory,
+		_ => Path::new("."),
+	};
+	std::fs::File::open(directory)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn file_contents_are_replaced() {
+		let directory = std::env::temp_dir().join(format!("atomic-file-test-{}", std::process::id()));
+		std::fs::create_dir_all(&directory).unwrap();
+		let path = directory.join("state.json");
+
+		write(&path, b"first").unwrap();
+		write(&path, b"second").unwrap();
+
+		assert_eq!(std::fs::read(&path).unwrap(), b"second");
+		assert!(!path.with_extension("tmp").exists());
+
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use futures::future::BoxFuture;
+use log::{debug, warn};
+use sp_runtime::traits::Header as HeaderT;
+use crate::{
+	runtime::{BlockHash, BlockNumber, Header},
+	substrate_client::{Client, Error},
+};
+
+/// Chain that is tracked by the best block tracker.
+pub trait Chain {
+	/// Get header of the block with given hash.
+	fn header(&self, hash: BlockHash) -> BoxFuture<'_, Result<Option<Header>, Error>>;
+	/// Get hash of the best chain block with given number.
+	fn block_hash(&self, number: BlockNumber) -> BoxFuture<'_, Result<Option<BlockHash>, Error>>;
+	/// Get generation of the connection to the node. Generation is changed when we're
+	/// switching to other node.
+	fn connection_generation(&self) -> u64;
+}
+
+/// Change of the best block.
+#[derive(Debug)]
+pub enum BestBlockChange {
+	/// Best block has been changed to its descendant.
+	Advance((BlockNumber, BlockHash)),
+	/// Best block has been changed to block of other fork.
+	Reorg {
+		/// Previously processed blocks that are not on the new best chain anymore.
+		retracted: Vec<(BlockNumber, BlockHash)>,
+		/// New best block.
+		best_block: (BlockNumber, BlockHash),
+	},
+}
+
+/// Tracks best (not necessarily finalized) chain and selects best block that has
+/// given number of confirmations. Detects reorganizations that replace blocks that
+/// have been already selected.
+pub struct BestBlockTracker<C = Client> {
+	/// Tracked chain.
+	chain: C,
+	/// Number of descendants that block must have to be selected.
+	confirmations: BlockNumber,
+	/// Known non-finalized headers: hash => (number, parent hash).
+	headers: HashMap<BlockHash, (BlockNumber, BlockHash)>,
+	/// Currently selected best block.
+	best_block: Option<(BlockNumber, BlockHash)>,
+	/// Generation of the connection that was active when best block has been selected.
+	best_block_generation: u64,
+}
+
+impl<C: Chain> BestBlockTracker<C> {
+	/// Create new tracker.
+	pub fn new(chain: C, confirmations: BlockNumber) -> Self {
+		BestBlockTracker {
+			chain,
+			confirmations,
+			headers: HashMap::new(),
+			best_block: None,
+			best_block_generation: 0,
+		}
+	}
+
+	/// Process new best header. Returns best block change, if any.
+	pub async fn on_new_head(&mut self, header: Header) -> Result<Option<BestBlockChange>, Error> {
+		let head_number = *header.number();
+		let head_hash = header.hash();
+		self.headers.insert(head_hash, (head_number, *header.parent_hash()));
+
+		if head_number < self.confirmations {
+			return Ok(None);
+		}
+
+		let mut new_best_block = (head_number, head_hash);
+		while new_best_block.0 > head_number - self.confirmations {
+			new_best_block = (new_best_block.0 - 1, self.parent_hash(new_best_block.1).await?);
+		}
+
+		let previous_best_block = match self.best_block {
+			Some(previous_best_block) if previous_best_block == new_best_block => return Ok(None),
+			Some(previous_best_block) => previous_best_block,
+			None => {
+				self.select_best_block(new_best_block);
+				return Ok(Some(BestBlockChange::Advance(new_best_block)));
+			},
+		};
+
+		// find common ancestor of previous and new best blocks, remembering retracted blocks
+		let mut retracted = Vec::new();
+		let mut old_branch = previous_best_block;
+		let mut new_branch = new_best_block;
+		while old_branch.1 != new_branch.1 {
+			if old_branch.0 >= new_branch.0 {
+				retracted.push(old_branch);
+				old_branch = (old_branch.0 - 1, self.parent_hash(old_branch.1).await?);
+			}
+			if new_branch.0 > old_branch.0 {
+				new_branch = (new_branch.0 - 1, self.parent_hash(new_branch.1).await?);
+			}
+		}
+
+		// new head could be behind the selected best block because node is lagging (i.e. after
+		// failover to other node) => keep the selected best block until the new chain overtakes
+		// it. Otherwise it is a reorganization to the shorter fork
+		if new_branch == new_best_block && !retracted.is_empty() && self.is_lagging(previous_best_block).await? {
+			debug!(
+				target: "secretstore",
+				"Ignoring new best block {} ({}): it is an ancestor of the current best block {} ({})",
+				new_best_block.0,
+				new_best_block.1,
+				previous_best_block.0,
+				previous_best_block.1,
+			);
+
+			return Ok(None);
+		}
+
+		self.select_best_block(new_best_block);
+		if retracted.is_empty() {
+			return Ok(Some(BestBlockChange::Advance(new_best_block)));
+		}
+
+		warn!(
+			target: "secretstore",
+			"Chain reorganization: {} processed blocks have been retracted. New best block: {} ({})",
+			retracted.len(),
+			new_best_block.0,
+			new_best_block.1,
+		);
+
+		Ok(Some(BestBlockChange::Reorg {
+			retracted,
+			best_block: new_best_block,
+		}))
+	}
+
+	/// Forget about headers that are below finalized block.
+	pub fn on_finalized(&mut self, finalized_number: BlockNumber) {
+		self.headers.retain(|_, (number, _)| *number >= finalized_number);
+	}
+
+	/// Remember selected best block.
+	fn select_best_block(&mut self, best_block: (BlockNumber, BlockHash)) {
+		self.best_block = Some(best_block);
+		self.best_block_generation = self.chain.connection_generation();
+	}
+
+	/// Returns true if node is lagging behind the selected best block, i.e. if the block is
+	/// still on the best chain of the node, or if we have switched to other node that has
+	/// not yet imported the block.
+	async fn is_lagging(&self, best_block: (BlockNumber, BlockHash)) -> Result<bool, Error> {
+		match self.chain.block_hash(best_block.0).await? {
+			Some(hash) => Ok(hash == best_block.1),
+			None => Ok(self.chain.connection_generation() != self.best_block_generation),
+		}
+	}
+
+	/// Get parent hash of the block, reading its header from the node if it is unknown.
+	async fn parent_hash(&mut self, hash: BlockHash) -> Result<BlockHash, Error> {
+		if let Some((_, parent_hash)) = self.headers.get(&hash) {
+			return Ok(*parent_hash);
+		}
+
+		let header = self.chain.header(hash).await?.ok_or(Error::UnknownBlockHash(hash))?;
+		let parent_hash = *header.parent_hash();
+		self.headers.insert(hash, (*header.number(), parent_hash));
+		Ok(parent_hash)
+	}
+}
+
+impl Chain for Client {
+	fn header(&self, hash: BlockHash) -> BoxFuture<'_, Result<Option<Header>, Error>> {
+		Box::pin(Client::header(self, hash))
+	}
+
+	fn block_hash(&self, number: BlockNumber) -> BoxFuture<'_, Result<Option<BlockHash>, Error>> {
+		Box::pin(Client::block_hash(self, number))
+	}
+
+	fn connection_generation(&self) -> u64 {
+		Client::connection_generation(self)
+	}
+}
+
+impl BestBlockChange {
+	/// Get new best block.
+	pub fn best_block(&self) -> (BlockNumber, BlockHash) {
+		match *self {
+			BestBlockChange::Advance(best_block) => best_block,
+			BestBlockChange::Reorg { best_block, .. } => best_block,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, Mutex};
+	use super::*;
+
+	#[derive(Default)]
+	struct TestChainData {
+		headers: HashMap<BlockHash, Header>,
+		best_chain: HashMap<BlockNumber, BlockHash>,
+		generation: u64,
+	}
+
+	#[derive(Clone, Default)]
+	struct TestChain(Arc<Mutex<TestChainData>>);
+
+	impl TestChain {
+		/// Import header and make it the best block of the node.
+		fn import(&self, parent: Option<&Header>, seed: u8) -> Header {
+			let header = Header::new(
+				parent.map(|parent| parent.number + 1).unwrap_or(0),
+				[seed; 32].into(),
+				Default::default(),
+				parent.map(|parent| parent.hash()).unwrap_or_default(),
+				Default::default(),
+			);
+			let mut data = self.0.lock().unwrap();
+			data.headers.insert(header.hash(), header.clone());
+			data.best_chain.retain(|number, _| *number < header.number);
+			data.best_chain.insert(header.number, header.hash());
+			header
+		}
+
+		/// Import linear chain of given length.
+		fn import_chain(&self, len: usize) -> Vec<Header> {
+			let mut headers: Vec<Header> = Vec::new();
+			for _ in 0..len {
+				let header = self.import(headers.last(), 0);
+				headers.push(header);
+			}
+			headers
+		}
+
+		/// Forget all best chain blocks after given number.
+		fn truncate_best_chain(&self, number: BlockNumber) {
+			self.0.lock().unwrap().best_chain.retain(|best_number, _| *best_number <= number);
+		}
+
+		fn failover(&self) {
+			self.0.lock().unwrap().generation += 1;
+		}
+	}
+
+	impl Chain for TestChain {
+		fn header(&self, hash: BlockHash) -> BoxFuture<'_, Result<Option<Header>, Error>> {
+			let header = self.0.lock().unwrap().headers.get(&hash).cloned();
+			Box::pin(futures::future::ready(Ok(header)))
+		}
+
+		fn block_hash(&self, number: BlockNumber) -> BoxFuture<'_, Result<Option<BlockHash>, Error>> {
+			let hash = self.0.lock().unwrap().best_chain.get(&number).cloned();
+			Box::pin(futures::future::ready(Ok(hash)))
+		}
+
+		fn connection_generation(&self) -> u64 {
+			self.0.lock().unwrap().generation
+		}
+	}
+
+	fn id(header: &Header) -> (BlockNumber, BlockHash) {
+		(header.number, header.hash())
+	}
+
+	fn on_new_head(tracker: &mut BestBlockTracker<TestChain>, header: &Header) -> Option<BestBlockChange> {
+		futures::executor::block_on(tracker.on_new_head(header.clone())).unwrap()
+	}
+
+	#[test]
+	fn best_block_is_advanced() {
+		let chain = TestChain::default();
+		let headers = chain.import_chain(4);
+		let mut tracker = BestBlockTracker::new(chain, 1);
+
+		match on_new_head(&mut tracker, &headers[2]) {
+			Some(BestBlockChange::Advance(best_block)) => assert_eq!(best_block, id(&headers[1])),
+			change => panic!("unexpected change: {:?}", change),
+		}
+		match on_new_head(&mut tracker, &headers[3]) {
+			Some(BestBlockChange::Advance(best_block)) => assert_eq!(best_block, id(&headers[2])),
+			change => panic!("unexpected change: {:?}", change),
+		}
+		assert!(on_new_head(&mut tracker, &headers[3]).is_none());
+	}
+
+	#[test]
+	fn reorg_to_longer_fork_is_detected() {
+		let chain = TestChain::default();
+		let headers = chain.import_chain(4);
+		let mut tracker = BestBlockTracker::new(chain.clone(), 0);
+		on_new_head(&mut tracker, &headers[3]);
+
+		let fork2 = chain.import(Some(&headers[1]), 1);
+		let fork3 = chain.import(Some(&fork2), 1);
+		let fork4 = chain.import(Some(&fork3), 1);
+		match on_new_head(&mut tracker, &fork4) {
+			Some(BestBlockChange::Reorg { retracted, best_block }) => {
+				assert_eq!(retracted, vec![id(&headers[3]), id(&headers[2])]);
+				assert_eq!(best_block, id(&fork4));
+			},
+			change => panic!("unexpected change: {:?}", change),
+		}
+	}
+
+	#[test]
+	fn ancestor_of_best_block_is_ignored_when_node_is_lagging() {
+		let chain = TestChain::default();
+		let headers = chain.import_chain(4);
+		let mut tracker = BestBlockTracker::new(chain.clone(), 0);
+		on_new_head(&mut tracker, &headers[3]);
+
+		// late notification: best block is still on the best chain of the node
+		assert!(on_new_head(&mut tracker, &headers[2]).is_none());
+
+		// failover to node that has not yet imported best block
+		chain.failover();
+		chain.truncate_best_chain(2);
+		assert!(on_new_head(&mut tracker, &headers[2]).is_none());
+
+		// new node has caught up
+		assert!(on_new_head(&mut tracker, &headers[3]).is_none());
+	}
+
+	#[test]
+	fn reorg_to_shorter_fork_is_detected() {
+		let chain = TestChain::default();
+		let headers = chain.import_chain(4);
+		let mut tracker = BestBlockTracker::new(chain.clone(), 0);
+		on_new_head(&mut tracker, &headers[3]);
+
+		// same node has switched to the shorter fork
+		chain.truncate_best_chain(2);
+		match on_new_head(&mut tracker, &headers[2]) {
+			Some(BestBlockChange::Reorg { retracted, best_block }) => {
+				assert_eq!(retracted, vec![id(&headers[3])]);
+				assert_eq!(best_block, id(&headers[2]));
+			},
+			change => panic!("unexpected change: {:?}", change),
+		}
+	}
+
+	#[test]
+	fn reorg_to_ancestor_is_detected_when_other_block_is_on_best_chain() {
+		let chain = TestChain::default();
+		let headers = chain.import_chain(4);
+		let mut tracker = BestBlockTracker::new(chain.clone(), 0);
+		on_new_head(&mut tracker, &headers[3]);
+
+		// node has switched to other fork, but we're notified about its ancestor first
+		chain.failover();
+		chain.import(Some(&headers[2]), 1);
+		match on_new_head(&mut tracker, &headers[2]) {
+			Some(BestBlockChange::Reorg { retracted, best_block }) => {
+				assert_eq!(retracted, vec![id(&headers[3])]);
+				assert_eq!(best_block, id(&headers[2]));
+			},
+			change => panic!("unexpected change: {:?}", change),
+		}
+	}
+}
@@ -1,10 +1,11 @@
-// TODO: replace block_on with ThreadPool + channel (we can't use async everywhere :/)
-
 use std::{
 	collections::BTreeSet,
 	ops::Range,
+	sync::Arc,
 };
-use log::error;
+use codec::Encode;
+use log::{error, warn};
+use parking_lot::RwLock;
 use parity_secretstore_primitives::{
 	Address, KeyServerId, ServerKeyId,
 };
@@ -12,6 +13,8 @@ use parity_secretstore_substrate_service::{
 	Blockchain, BlockchainServiceTask, MaybeSecretStoreEvent,
 };
 use crate::{
+	async_bridge::AsyncBridge,
+	checkpoint::{ProcessedBlocks, ServiceRequest},
 	substrate_client::Client,
 };
 
@@ -20,18 +23,87 @@ pub struct SecretStoreBlockchain {
 	/// RPC client that can call RPC on full (presumably archive node) that
 	/// is synching the blockhain.
 	client: Client,
+	/// Bridge that is used to call async client methods.
+	bridge: AsyncBridge,
+	/// Processed block and cached key servers set.
+	data: RwLock<Data>,
+	/// Blocks and requests that are processed by the service.
+	processed_blocks: Arc<ProcessedBlocks>,
+}
+
+/// Mutable blockchain data.
+#[derive(Default)]
+struct Data {
+	/// Hash of the block that is currently processed by the service.
+	processed_block: Option<crate::runtime::BlockHash>,
+	/// Last successfully read key servers set and block where it has been read.
+	current_key_servers_set: Option<(crate::runtime::BlockHash, BTreeSet<KeyServerId>)>,
 }
 
 /// Runtime event wrapper.
-pub struct SecretStoreEvent(crate::runtime::Event);
+pub struct SecretStoreEvent(crate::metadata::Event);
 
 impl SecretStoreBlockchain {
-	///
-	pub fn new(client: Client) -> SecretStoreBlockchain {
+	/// Create new blockchain.
+	pub fn new(client: Client, bridge: AsyncBridge, processed_blocks: Arc<ProcessedBlocks>) -> SecretStoreBlockchain {
 		SecretStoreBlockchain {
 			client,
+			bridge,
+			data: RwLock::new(Data::default()),
+			processed_blocks,
 		}
 	}
+
+	/// Get hash of the block that is currently processed by the service.
+	fn processed_block(&self) -> Result<crate::runtime::BlockHash, String> {
+		self.data.read().processed_block.ok_or_else(|| "no block has been processed yet".into())
+	}
+
+	/// Read range of pending service tasks at given block.
+	fn pending_tasks(
+		&self,
+		block_hash: crate::runtime::BlockHash,
+		method: &'static str,
+		range: Range<usize>,
+	) -> Result<Vec<SecretStoreEvent>, String> {
+		let client = self.client.clone();
+		let events: Vec<substrate_secret_store_runtime::Event> = self.bridge.run(async move {
+			client.call_runtime_method(
+				block_hash,
+				method,
+				serialize_range(range),
+			).await
+		}).map_err(|error| format!("{:?}", error))?;
+		Ok(events
+			.into_iter()
+			.map(|event| SecretStoreEvent(crate::metadata::Event::SecretStore(event)))
+			.collect())
+	}
+
+	/// Check if service response is required at the block that is currently processed.
+	/// If response is not required, request (if tracked) is completed.
+	fn is_response_required(
+		&self,
+		method: &'static str,
+		arguments: Vec<Vec<u8>>,
+		request: Option<ServiceRequest>,
+	) -> Result<bool, String> {
+		let block_hash = self.processed_block()?;
+		let client = self.client.clone();
+		let is_response_required = self.bridge.run(async move {
+			client.call_runtime_method(
+				block_hash,
+				method,
+				arguments,
+			).await
+		}).map_err(|error| format!("{:?}", error))?;
+
+		if let (false, Some(request)) = (is_response_required, request) {
+			self.processed_blocks.on_request_completed(request);
+		}
+
+		Ok(is_response_required)
+	}
 }
 
 impl Blockchain for SecretStoreBlockchain {
@@ -41,15 +113,23 @@ impl Blockchain for SecretStoreBlockchain {
 	type PendingEvents = Vec<SecretStoreEvent>;
 
 	fn block_events(&self, block_hash: Self::BlockHash) -> Self::BlockEvents {
-		let events = futures::executor::block_on(
-			self.client.header_events(block_hash)
-		);
+		self.data.write().processed_block = Some(block_hash);
+
+		let client = self.client.clone();
+		let events = self.bridge.run(async move {
+			client.header_events(block_hash).await
+		});
 
 		match events {
-			Ok(events) => events
-				.into_iter()
-				.map(|event| SecretStoreEvent(event.event))
-				.collect(),
+			Ok(events) => {
+				let events = events
+					.into_iter()
+					.map(|event| SecretStoreEvent(event.event))
+					.collect::<Vec<_>>();
+				let requests = events.iter().filter_map(SecretStoreEvent::service_request).collect();
+				self.processed_blocks.on_block_events(block_hash, requests);
+				events
+			},
 			Err(error) => {
 				error!(
 					target: "secretstore",
@@ -58,13 +138,56 @@ impl Blockchain for SecretStoreBlockchain {
 					error,
 				);
 
+				self.processed_blocks.on_block_failed(block_hash);
+
 				return Vec::new();
 			}
 		}
 	}
 
 	fn current_key_servers_set(&self) -> BTreeSet<KeyServerId> {
-		unimplemented!()
+		let (block_hash, last_known_set) = {
+			let data = self.data.read();
+			match (data.processed_block, data.current_key_servers_set.as_ref()) {
+				(Some(block_hash), Some((set_block_hash, set))) if block_hash == *set_block_hash => return set.clone(),
+				(Some(block_hash), set) => (block_hash, set.map(|(_, set)| set.clone())),
+				(None, set) => {
+					warn!(
+						target: "secretstore",
+						"Current key servers set is requested before any block has been processed",
+					);
+
+					return set.map(|(_, set)| set.clone()).unwrap_or_default();
+				},
+			}
+		};
+
+		let client = self.client.clone();
+		let current_set: Result<Vec<KeyServerId>, _> = self.bridge.run(async move {
+			client.call_runtime_method(
+				block_hash,
+				"SecretStoreServiceApi_current_key_servers_set",
+				vec![],
+			).await
+		});
+
+		match current_set {
+			Ok(current_set) => {
+				let current_set = current_set.into_iter().collect::<BTreeSet<_>>();
+				self.data.write().current_key_servers_set = Some((block_hash, current_set.clone()));
+				current_set
+			},
+			Err(error) => {
+				error!(
+					target: "secretstore",
+					"Failed to read current key servers set at block {}: {:?}. Using last known set",
+					block_hash,
+					error,
+				);
+
+				last_known_set.unwrap_or_default()
+			},
+		}
 	}
 
 	fn server_key_generation_tasks(
@@ -72,86 +195,131 @@ impl Blockchain for SecretStoreBlockchain {
 		block_hash: Self::BlockHash,
 		range: Range<usize>,
 	) -> Result<Self::PendingEvents, String> {
-		let events: Vec<substrate_secret_store_runtime::Event> = futures::executor::block_on(async {
-			self.client.call_runtime_method(
-				block_hash,
-				"SecretStoreServiceApi_server_key_generation_tasks",
-				serialize_range(range),
-			).await
-		}).map_err(|error| format!("{:?}", error))?;
-		Ok(events
-			.into_iter()
-			.map(|event| SecretStoreEvent(crate::runtime::Event::substrate_secret_store_runtime(event)))
-			.collect())
+		self.pending_tasks(
+			block_hash,
+			"SecretStoreServiceApi_server_key_generation_tasks",
+			range,
+		)
 	}
 
 	fn is_server_key_generation_response_required(
 		&self,
-		_key_id: ServerKeyId,
-		_key_server_id: KeyServerId,
+		key_id: ServerKeyId,
+		key_server_id: KeyServerId,
 	) -> Result<bool, String> {
-		unimplemented!()
+		self.is_response_required(
+			"SecretStoreServiceApi_is_server_key_generation_response_required",
+			vec![key_id.encode(), key_server_id.encode()],
+			Some(ServiceRequest::ServerKeyGeneration(key_id)),
+		)
 	}
 
 	fn server_key_retrieval_tasks(
 		&self,
-		_block_hash: Self::BlockHash,
-		_range: Range<usize>,
+		block_hash: Self::BlockHash,
+		range: Range<usize>,
 	) -> Result<Self::PendingEvents, String> {
-		unimplemented!()
+		self.pending_tasks(
+			block_hash,
+			"SecretStoreServiceApi_server_key_retrieval_tasks",
+			range,
+		)
 	}
 
 	fn is_server_key_retrieval_response_required(
 		&self,
-		_key_id: ServerKeyId,
-		_key_server_id: KeyServerId,
+		key_id: ServerKeyId,
+		key_server_id: KeyServerId,
 	) -> Result<bool, String> {
-		unimplemented!()
+		self.is_response_required(
+			"SecretStoreServiceApi_is_server_key_retrieval_response_required",
+			vec![key_id.encode(), key_server_id.encode()],
+			Some(ServiceRequest::ServerKeyRetrieval(key_id)),
+		)
 	}
 
 	fn document_key_store_tasks(
 		&self,
-		_block_hash: Self::BlockHash,
-		_range: Range<usize>,
+		block_hash: Self::BlockHash,
+		range: Range<usize>,
 	) -> Result<Self::PendingEvents, String> {
-		unimplemented!()
+		self.pending_tasks(
+			block_hash,
+			"SecretStoreServiceApi_document_key_store_tasks",
+			range,
+		)
 	}
 
 	fn is_document_key_store_response_required(
 		&self,
-		_key_id: ServerKeyId,
-		_key_server_id: KeyServerId,
+		key_id: ServerKeyId,
+		key_server_id: KeyServerId,
 	) -> Result<bool, String> {
-		unimplemented!()
+		self.is_response_required(
+			"SecretStoreServiceApi_is_document_key_store_response_required",
+			vec![key_id.encode(), key_server_id.encode()],
+			Some(ServiceRequest::DocumentKeyStore(key_id)),
+		)
 	}
 
 	fn document_key_shadow_retrieval_tasks(
 		&self,
-		_block_hash: Self::BlockHash,
-		_range: Range<usize>,
+		block_hash: Self::BlockHash,
+		range: Range<usize>,
 	) -> Result<Self::PendingEvents, String> {
-		unimplemented!()
+		self.pending_tasks(
+			block_hash,
+			"SecretStoreServiceApi_document_key_shadow_retrieval_tasks",
+			range,
+		)
 	}
 
 	fn is_document_key_shadow_retrieval_response_required(
 		&self,
-		_key_id: ServerKeyId,
-		_requester: Address,
-		_key_server_id: KeyServerId,
+		key_id: ServerKeyId,
+		requester: Address,
+		key_server_id: KeyServerId,
 	) -> Result<bool, String> {
-		unimplemented!()
+		self.is_response_required(
+			"SecretStoreServiceApi_is_document_key_shadow_retrieval_response_required",
+			vec![key_id.encode(), requester.encode(), key_server_id.encode()],
+			None,
+		)
+	}
+}
+
+impl SecretStoreEvent {
+	/// Get service request that is emitted by this event. Document key shadow retrieval
+	/// requests are not tracked.
+	fn service_request(&self) -> Option<ServiceRequest> {
+		match self.0 {
+			crate::metadata::Event::SecretStore(ref event) => match *event {
+				substrate_secret_store_runtime::Event::ServerKeyGenerationRequested(key_id, _, _) =>
+					Some(ServiceRequest::ServerKeyGeneration(key_id.into())),
+				substrate_secret_store_runtime::Event::ServerKeyRetrievalRequested(key_id) =>
+					Some(ServiceRequest::ServerKeyRetrieval(key_id.into())),
+				substrate_secret_store_runtime::Event::DocumentKeyStoreRequested(key_id, _, _, _) =>
+					Some(ServiceRequest::DocumentKeyStore(key_id.into())),
+				_ => None,
+			},
+			_ => None,
+		}
 	}
 }
 
 impl MaybeSecretStoreEvent for SecretStoreEvent {
 	fn as_secret_store_event(self) -> Option<substrate_secret_store_runtime::Event> {
 		match self.0 {
-			crate::runtime::Event::substrate_secret_store_runtime(event) => Some(event),
+			crate::metadata::Event::SecretStore(event) => Some(event),
 			_ => None,
 		}
 	}
 }
 
+/// Serialize range of tasks as runtime method arguments.
 fn serialize_range(range: Range<usize>) -> Vec<Vec<u8>> {
-	unimplemented!()
+	vec![
+		(range.start as u32).encode(),
+		(range.end as u32).encode(),
+	]
 }
\ No newline at end of file
@@ -0,0 +1,375 @@
+use std::{
+	collections::VecDeque,
+	path::PathBuf,
+};
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use parity_secretstore_primitives::ServerKeyId;
+use serde::{Deserialize, Serialize};
+use sp_runtime::traits::Header as HeaderT;
+use crate::{
+	runtime::{BlockHash, BlockNumber},
+	substrate_client::{Client, Error},
+};
+
+/// Last fully processed block, as it is stored in the checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct PersistentCheckpoint {
+	/// Block number.
+	number: BlockNumber,
+	/// Hex-encoded block hash.
+	hash: String,
+}
+
+/// File where last fully processed block is stored.
+pub struct Checkpoint {
+	/// Path to the checkpoint file.
+	path: PathBuf,
+}
+
+impl Checkpoint {
+	/// Create checkpoint, stored at given path.
+	pub fn new(path: PathBuf) -> Self {
+		Checkpoint {
+			path,
+		}
+	}
+
+	/// Read last fully processed block.
+	pub fn read(&self) -> Option<(BlockNumber, BlockHash)> {
+		let contents = match std::fs::read_to_string(&self.path) {
+			Ok(contents) => contents,
+			Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => return None,
+			Err(error) => {
+				error!(
+					target: "secretstore",
+					"Failed to read checkpoint file {}: {}",
+					self.path.display(),
+					error,
+				);
+
+				return None;
+			},
+		};
+
+		let checkpoint = serde_json::from_str::<PersistentCheckpoint>(&contents)
+			.map_err(|error| error.to_string())
+			.and_then(|checkpoint| checkpoint.hash.trim_start_matches("0x").parse()
+				.map(|hash| (checkpoint.number, hash))
+				.map_err(|error| format!("{:?}", error)));
+		match checkpoint {
+			Ok(checkpoint) => Some(checkpoint),
+			Err(error) => {
+				warn!(
+					target: "secretstore",
+					"Ignoring invalid checkpoint file {}: {}",
+					self.path.display(),
+					error,
+				);
+
+				None
+			},
+		}
+	}
+
+	/// Remember last fully processed block.
+	pub fn write(&self, block: (BlockNumber, BlockHash)) {
+		let checkpoint = PersistentCheckpoint {
+			number: block.0,
+			hash: format!("{:x}", block.1),
+		};
+
+		let write_result = serde_json::to_vec(&checkpoint)
+			.map_err(|error| error.to_string())
+			.and_then(|contents| crate::atomic_file::write(&self.path, &contents).map_err(|error| error.to_string()));
+		if let Err(error) = write_result {
+			error!(
+				target: "secretstore",
+				"Failed to write checkpoint file {}: {}",
+				self.path.display(),
+				error,
+			);
+		}
+	}
+}
+
+/// Service request that has been emitted in the block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServiceRequest {
+	/// Server key generation request.
+	ServerKeyGeneration(ServerKeyId),
+	/// Server key retrieval request.
+	ServerKeyRetrieval(ServerKeyId),
+	/// Document key store request.
+	DocumentKeyStore(ServerKeyId),
+}
+
+/// Blocks that have been passed to the service, but not yet processed. Block is processed
+/// when its events have been read and the service has completed all requests from the block.
+pub struct ProcessedBlocks {
+	/// Checkpoint file (if configured).
+	checkpoint: Option<Checkpoint>,
+	/// Mutable data.
+	data: Mutex<ProcessedBlocksData>,
+}
+
+/// Mutable data of processed blocks tracker.
+#[derive(Default)]
+struct ProcessedBlocksData {
+	/// Blocks that have been passed to the service, in order.
+	pending: VecDeque<PendingBlock>,
+	/// True if the service has failed to process some block. Checkpoint is not advanced
+	/// after such block, so that it is replayed after restart.
+	is_stalled: bool,
+}
+
+/// Block that has been passed to the service.
+struct PendingBlock {
+	/// Block number and hash.
+	block: (BlockNumber, BlockHash),
+	/// Requests from the block that are not yet completed. None if block events
+	/// have not been read yet.
+	requests: Option<Vec<ServiceRequest>>,
+}
+
+impl ProcessedBlocks {
+	/// Create tracker that writes processed blocks to given checkpoint.
+	pub fn new(checkpoint: Option<Checkpoint>) -> Self {
+		ProcessedBlocks {
+			checkpoint,
+			data: Mutex::new(ProcessedBlocksData::default()),
+		}
+	}
+
+	/// Remember block that is passed to the service.
+	pub fn on_block_scheduled(&self, block: (BlockNumber, BlockHash)) {
+		self.data.lock().on_block_scheduled(block);
+	}
+
+	/// Called when events of the block have been read. Requests from the block are
+	/// tracked until the service completes them.
+	pub fn on_block_events(&self, block_hash: BlockHash, requests: Vec<ServiceRequest>) {
+		let mut data = self.data.lock();
+		data.on_block_events(block_hash, requests);
+		self.write_checkpoint(data.advance());
+	}
+
+	/// Called when the service has completed the request, i.e. when response has been
+	/// submitted or when response is not required anymore.
+	pub fn on_request_completed(&self, request: ServiceRequest) {
+		let mut data = self.data.lock();
+		data.on_request_completed(request);
+		self.write_checkpoint(data.advance());
+	}
+
+	/// Called when the service has failed to process the block. Checkpoint is never advanced
+	/// after this, so that the block is replayed after restart.
+	pub fn on_block_failed(&self, block_hash: BlockHash) {
+		let mut data = self.data.lock();
+		if data.is_stalled {
+			return;
+		}
+
+		data.stall();
+		error!(
+			target: "secretstore",
+			"Failed to process block {}. Checkpoint will not be advanced until restart",
+			block_hash,
+		);
+	}
+
+	/// Write last processed block to the checkpoint file. Called under data lock, so that
+	/// checkpoint is never moved back.
+	fn write_checkpoint(&self, processed_block: Option<(BlockNumber, BlockHash)>) {
+		if let (Some(checkpoint), Some(processed_block)) = (self.checkpoint.as_ref(), processed_block) {
+			checkpoint.write(processed_block);
+		}
+	}
+}
+
+impl ProcessedBlocksData {
+	/// Remember block that is passed to the service.
+	fn on_block_scheduled(&mut self, block: (BlockNumber, BlockHash)) {
+		if !self.is_stalled {
+			self.pending.push_back(PendingBlock {
+				block,
+				requests: None,
+			});
+		}
+	}
+
+	/// Remember requests from the block.
+	fn on_block_events(&mut self, block_hash: BlockHash, requests: Vec<ServiceRequest>) {
+		if let Some(pending_block) = self.pending.iter_mut().find(|pending_block| pending_block.block.1 == block_hash) {
+			pending_block.requests = Some(requests);
+		}
+	}
+
+	/// Forget completed request of the oldest block that has emitted it.
+	fn on_request_completed(&mut self, request: ServiceRequest) {
+		for pending_block in self.pending.iter_mut() {
+			if let Some(ref mut requests) = pending_block.requests {
+				if let Some(position) = requests.iter().position(|pending_request| *pending_request == request) {
+					requests.swap_remove(position);
+					return;
+				}
+			}
+		}
+	}
+
+	/// Stop tracking blocks.
+	fn stall(&mut self) {
+		self.is_stalled = true;
+		self.pending.clear();
+	}
+
+	/// Forget leading blocks that have been processed. Returns last of these blocks.
+	fn advance(&mut self) -> Option<(BlockNumber, BlockHash)> {
+		let mut processed_block = None;
+		while self.pending.front().map(PendingBlock::is_processed).unwrap_or(false) {
+			processed_block = self.pending.pop_front().map(|pending_block| pending_block.block);
+		}
+		processed_block
+	}
+}
+
+impl PendingBlock {
+	/// Returns true if block events have been read and all requests have been completed.
+	fn is_processed(&self) -> bool {
+		self.requests.as_ref().map(|requests| requests.is_empty()).unwrap_or(false)
+	}
+}
+
+/// Select number of the last processed block, after which finalized blocks are processed,
+/// and number of the last replayed block (i.e. finalized head at the moment of start).
+/// Blocks between checkpoint and current finalized head are replayed, but no more than
+/// `max_catch_up_blocks` of them.
+pub async fn replay_start(
+	client: &Client,
+	checkpoint: Option<(BlockNumber, BlockHash)>,
+	max_catch_up_blocks: BlockNumber,
+) -> Result<Option<(BlockNumber, BlockNumber)>, Error> {
+	let (checkpoint_number, checkpoint_hash) = match checkpoint {
+		Some(checkpoint) => checkpoint,
+		None => return Ok(None),
+	};
+
+	if client.block_hash(checkpoint_number).await? != Some(checkpoint_hash) {
+		warn!(
+			target: "secretstore",
+			"Checkpoint block {} ({}) is not on the finalized chain. Ignoring checkpoint",
+			checkpoint_number,
+			checkpoint_hash,
+		);
+
+		return Ok(None);
+	}
+
+	let finalized_hash = client.finalized_head().await?;
+	let finalized_number = *client.header(finalized_hash).await?
+		.ok_or(Error::UnknownBlockHash(finalized_hash))?
+		.number();
+	if finalized_number <= checkpoint_number {
+		return Ok(Some((checkpoint_number, checkpoint_number)));
+	}
+
+	let replay_start = if finalized_number - checkpoint_number > max_catch_up_blocks {
+		warn!(
+			target: "secretstore",
+			"Too many blocks have been finalized since checkpoint block {}. Only last {} blocks will be replayed",
+			checkpoint_number,
+			max_catch_up_blocks,
+		);
+
+		finalized_number - max_catch_up_blocks
+	} else {
+		checkpoint_number
+	};
+
+	info!(
+		target: "secretstore",
+		"Replaying finalized blocks {}..={}",
+		replay_start + 1,
+		finalized_number,
+	);
+
+	Ok(Some((replay_start, finalized_number)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn block(number: u8) -> (BlockNumber, BlockHash) {
+		(number as BlockNumber, [number; 32].into())
+	}
+
+	fn request(index: u8) -> ServiceRequest {
+		ServiceRequest::ServerKeyGeneration([index; 32].into())
+	}
+
+	fn data_with_blocks(count: u8) -> ProcessedBlocksData {
+		let mut data = ProcessedBlocksData::default();
+		for number in 1..=count {
+			data.on_block_scheduled(block(number));
+		}
+		data
+	}
+
+	#[test]
+	fn block_without_requests_is_processed_when_events_are_read() {
+		let mut data = data_with_blocks(2);
+		assert_eq!(data.advance(), None);
+
+		data.on_block_events(block(1).1, vec![]);
+		assert_eq!(data.advance(), Some(block(1)));
+		assert_eq!(data.advance(), None);
+	}
+
+	#[test]
+	fn block_is_processed_when_all_requests_are_completed() {
+		let mut data = data_with_blocks(1);
+		data.on_block_events(block(1).1, vec![request(1), request(2)]);
+
+		data.on_request_completed(request(1));
+		assert_eq!(data.advance(), None);
+
+		data.on_request_completed(request(2));
+		assert_eq!(data.advance(), Some(block(1)));
+	}
+
+	#[test]
+	fn checkpoint_is_not_advanced_over_incomplete_block() {
+		let mut data = data_with_blocks(3);
+		data.on_block_events(block(1).1, vec![request(1)]);
+		data.on_block_events(block(2).1, vec![]);
+		data.on_block_events(block(3).1, vec![]);
+		assert_eq!(data.advance(), None);
+
+		data.on_request_completed(request(1));
+		assert_eq!(data.advance(), Some(block(3)));
+	}
+
+	#[test]
+	fn request_of_oldest_block_is_completed_first() {
+		let mut data = data_with_blocks(2);
+		data.on_block_events(block(1).1, vec![request(1)]);
+		data.on_block_events(block(2).1, vec![request(1)]);
+
+		data.on_request_completed(request(1));
+		assert_eq!(data.advance(), Some(block(1)));
+
+		data.on_request_completed(request(1));
+		assert_eq!(data.advance(), Some(block(2)));
+	}
+
+	#[test]
+	fn blocks_are_not_tracked_after_failure() {
+		let mut data = data_with_blocks(2);
+		data.stall();
+		data.on_block_scheduled(block(3));
+		data.on_block_events(block(3).1, vec![]);
+
+		assert_eq!(data.advance(), None);
+	}
+}
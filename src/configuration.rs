@@ -0,0 +1,836 @@
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use parity_crypto::publickey::KeyPair;
+use parity_secretstore_primitives::Address;
+use serde::Deserialize;
+use crate::{
+	fee_policy::{FeePolicy, TipPolicy},
+	key_storage_encryption::SealingKeySource,
+	keystore,
+	signer::{SignatureScheme, Signer, SignerSource},
+};
+
+/// Default Substrate node host.
+const DEFAULT_SUBSTRATE_HOST: &'static str = "localhost";
+/// Default Substrate node RPC port.
+const DEFAULT_SUBSTRATE_PORT: u16 = 11011;
+/// Default number of blocks (after the latest finalized block) while signed transactions are valid.
+const DEFAULT_TRANSACTION_ERA: u64 = 64;
+/// Minimal period of mortal transaction era.
+const MIN_TRANSACTION_ERA: u64 = 4;
+/// Maximal period of mortal transaction era.
+const MAX_TRANSACTION_ERA: u64 = 1 << 16;
+/// Default key server network interface.
+const DEFAULT_LISTEN_ADDRESS: &'static str = "127.0.0.1";
+/// Default key server network port.
+const DEFAULT_LISTEN_PORT: u16 = 10_000;
+/// Default admin endpoint network interface.
+const DEFAULT_ADMIN_LISTEN_ADDRESS: &'static str = "127.0.0.1";
+/// Default max number of concurrently active service sessions.
+const DEFAULT_MAX_ACTIVE_SESSIONS: usize = 4;
+/// Default max number of finalized blocks that are replayed after restart.
+const DEFAULT_MAX_CATCH_UP_BLOCKS: u32 = 14_400;
+/// Default interval (in seconds) between pending service tasks restarts.
+const DEFAULT_PENDING_RESTART_INTERVAL: u64 = 10 * 60;
+
+/// All possible errors that can occur when reading configuration.
+#[derive(Debug)]
+pub enum Error {
+	/// Failed to read file.
+	Io(PathBuf, std::io::Error),
+	/// Failed to parse configuration file.
+	Toml(PathBuf, toml::de::Error),
+	/// Required option is missing.
+	MissingOption(&'static str),
+	/// Option has invalid value.
+	InvalidOption(&'static str, String),
+	/// Failed to load key from keystore file.
+	Keystore(keystore::Error),
+	/// Failed to load transactions signer.
+	Signer(crate::signer::Error),
+}
+
+/// Command to execute.
+pub enum Command {
+	/// Run key server.
+	Run(Configuration),
+	/// Generate new keystore file.
+	GenerateKeystore {
+		/// Path of the keystore file.
+		path: PathBuf,
+		/// Path to file with keystore password.
+		password_file: PathBuf,
+		/// Path to file with hex-encoded secret. Random secret is generated if not specified.
+		secret_file: Option<PathBuf>,
+	},
+	/// Print keystore file information.
+	InspectKeystore {
+		/// Path of the keystore file.
+		path: PathBuf,
+		/// Path to file with keystore password. If specified, keystore is decrypted.
+		password_file: Option<PathBuf>,
+	},
+}
+
+/// Validated node configuration.
+pub struct Configuration {
+	/// Substrate node connection parameters.
+	pub substrate: SubstrateConfiguration,
+	/// Transactions signer.
+	pub signer: SignerConfiguration,
+	/// Key server parameters.
+	pub key_server: KeyServerConfiguration,
+	/// Key storage parameters.
+	pub key_storage: KeyStorageConfiguration,
+	/// Substrate service parameters.
+	pub service: ServiceConfiguration,
+	/// Admin endpoint parameters.
+	pub admin: AdminConfiguration,
+}
+
+/// Substrate node connection parameters.
+pub struct SubstrateConfiguration {
+	/// Substrate nodes RPC endpoints (`host:port`), in order of preference.
+	pub endpoints: Vec<String>,
+	/// Number of blocks while signed transactions are valid. Transactions are immortal if None.
+	pub transaction_era: Option<u64>,
+	/// Transactions fee policy.
+	pub fee_policy: FeePolicy,
+	/// If specified, best block is selected from best (not finalized) chain and must have
+	/// given number of confirmations. Otherwise, best finalized block is used.
+	pub best_block_confirmations: Option<u32>,
+}
+
+/// Transactions signer parameters.
+pub struct SignerConfiguration {
+	/// Signer key pair.
+	pub signer: Signer,
+}
+
+/// Key server parameters.
+pub struct KeyServerConfiguration {
+	/// Key server key pair.
+	pub key_pair: KeyPair,
+	/// Network interface to listen on.
+	pub listen_address: String,
+	/// Network port to listen on.
+	pub listen_port: u16,
+	/// Address of administrator (if any).
+	pub admin_address: Option<Address>,
+	/// Path to the file where last sent migration transactions are stored. They are
+	/// only stored in memory if not specified.
+	pub migration_state_file: Option<PathBuf>,
+}
+
+/// Key storage parameters.
+pub struct KeyStorageConfiguration {
+	/// Path to the key storage database. Keys are stored in memory if not specified.
+	pub path: Option<PathBuf>,
+	/// Key that is used to seal key shares. Shares are stored unencrypted if not specified.
+	pub sealing_key: Option<SealingKeySource>,
+	/// Previous sealing keys that are only used to unseal key shares.
+	pub previous_sealing_keys: Vec<SealingKeySource>,
+	/// File with key shares that are imported when database is created.
+	pub import_file: Option<PathBuf>,
+}
+
+/// Substrate service parameters.
+pub struct ServiceConfiguration {
+	/// Max number of concurrently active service sessions.
+	pub max_active_sessions: Option<usize>,
+	/// Interval between pending service tasks restarts.
+	pub pending_restart_interval: Option<Duration>,
+	/// Path to the file where last processed block is stored. Blocks that have been
+	/// finalized while key server was down are not processed if not specified.
+	pub checkpoint_file: Option<PathBuf>,
+	/// Max number of finalized blocks that are replayed after restart.
+	pub max_catch_up_blocks: u32,
+}
+
+/// Admin endpoint parameters.
+pub struct AdminConfiguration {
+	/// Network interface (`address:port`) to listen on. Admin endpoint is disabled if None.
+	pub listen_address: Option<String>,
+}
+
+/// Configuration file contents.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigurationFile {
+	substrate: Option<SubstrateSection>,
+	signer: Option<SignerSection>,
+	key_server: Option<KeyServerSection>,
+	key_storage: Option<KeyStorageSection>,
+	service: Option<ServiceSection>,
+	admin: Option<AdminSection>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubstrateSection {
+	host: Option<String>,
+	port: Option<u16>,
+	endpoints: Option<Vec<String>>,
+	transaction_era: Option<u64>,
+	tip: Option<BalanceValue>,
+	tip_fee_percent: Option<u32>,
+	tip_escalation_percent: Option<u32>,
+	max_tip: Option<BalanceValue>,
+	min_balance: Option<BalanceValue>,
+	best_block_confirmations: Option<u32>,
+}
+
+/// Balance in configuration file. Balances that do not fit into TOML integer
+/// are specified as decimal strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BalanceValue {
+	Integer(u64),
+	String(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignerSection {
+	scheme: Option<String>,
+	uri: Option<String>,
+	mnemonic_file: Option<PathBuf>,
+	keystore_file: Option<PathBuf>,
+	password_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyServerSection {
+	secret: Option<String>,
+	secret_file: Option<PathBuf>,
+	keystore_file: Option<PathBuf>,
+	keystore_password_file: Option<PathBuf>,
+	listen_address: Option<String>,
+	listen_port: Option<u16>,
+	admin_address: Option<String>,
+	migration_state_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyStorageSection {
+	path: Option<PathBuf>,
+	sealing_key_file: Option<PathBuf>,
+	sealing_passphrase_file: Option<PathBuf>,
+	#[serde(default)]
+	previous_sealing_key_files: Vec<PathBuf>,
+	#[serde(default)]
+	previous_sealing_passphrase_files: Vec<PathBuf>,
+	import_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServiceSection {
+	max_active_sessions: Option<usize>,
+	pending_restart_interval: Option<u64>,
+	checkpoint_file: Option<PathBuf>,
+	max_catch_up_blocks: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdminSection {
+	listen_address: Option<String>,
+	listen_port: Option<u16>,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match *self {
+			Error::Io(ref path, ref error) => write!(f, "failed to read {}: {}", path.display(), error),
+			Error::Toml(ref path, ref error) => write!(f, "failed to parse {}: {}", path.display(), error),
+			Error::MissingOption(option) => write!(f, "missing required option: {}", option),
+			Error::InvalidOption(option, ref error) => write!(f, "invalid {} option: {}", option, error),
+			Error::Keystore(ref error) => write!(f, "failed to load key server key: {}", error),
+			Error::Signer(ref error) => write!(f, "failed to load transactions signer: {}", error),
+		}
+	}
+}
+
+/// Parse command line arguments (and configuration file, if specified).
+pub fn parse() -> Result<Command, Error> {
+	let matches = app().get_matches();
+	if let Some(keystore_matches) = matches.subcommand_matches("keystore") {
+		return Ok(match keystore_matches.subcommand() {
+			("generate", Some(matches)) => Command::GenerateKeystore {
+				path: required_path(matches, "path")?,
+				password_file: required_path(matches, "password-file")?,
+				secret_file: matches.value_of("secret-file").map(Into::into),
+			},
+			("inspect", Some(matches)) => Command::InspectKeystore {
+				path: required_path(matches, "path")?,
+				password_file: matches.value_of("password-file").map(Into::into),
+			},
+			_ => unreachable!("SubcommandRequired is set for keystore command; qed"),
+		});
+	}
+
+	let file = match matches.value_of("config") {
+		Some(path) => read_configuration_file(Path::new(path))?,
+		None => ConfigurationFile::default(),
+	};
+
+	build_configuration(&matches, file).map(Command::Run)
+}
+
+/// Command line arguments definition.
+fn app() -> App<'static, 'static> {
+	App::new("substrate-secret-store")
+		.about("Secret Store key server connected to Substrate node")
+		.arg(Arg::with_name("config")
+			.long("config")
+			.value_name("PATH")
+			.help("Path to the TOML configuration file")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-host")
+			.long("sub-host")
+			.value_name("HOST")
+			.help("Substrate node host")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-port")
+			.long("sub-port")
+			.value_name("PORT")
+			.help("Substrate node RPC port")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-endpoint")
+			.long("sub-endpoint")
+			.value_name("HOST:PORT")
+			.help("Substrate node RPC endpoint. May be specified multiple times to fail over between nodes")
+			.takes_value(true)
+			.multiple(true)
+			.number_of_values(1)
+			.conflicts_with_all(&["sub-host", "sub-port"]))
+		.arg(Arg::with_name("sub-transaction-era")
+			.long("sub-transaction-era")
+			.value_name("BLOCKS")
+			.help("Number of blocks while signed transactions are valid. 0 means that transactions never expire")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-best-block-confirmations")
+			.long("sub-best-block-confirmations")
+			.value_name("BLOCKS")
+			.help("Follow best (not finalized) chain and use block with given number of confirmations as best block")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-tip")
+			.long("sub-tip")
+			.value_name("BALANCE")
+			.help("Fixed tip that is paid for every transaction")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-tip-fee-percent")
+			.long("sub-tip-fee-percent")
+			.value_name("PERCENT")
+			.help("Tip that is paid for every transaction, as a percentage of the estimated transaction fee")
+			.takes_value(true)
+			.conflicts_with("sub-tip"))
+		.arg(Arg::with_name("sub-tip-escalation-percent")
+			.long("sub-tip-escalation-percent")
+			.value_name("PERCENT")
+			.help("Percent by which tip is increased every time transaction is resubmitted")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-max-tip")
+			.long("sub-max-tip")
+			.value_name("BALANCE")
+			.help("Tip is never increased above this value")
+			.takes_value(true))
+		.arg(Arg::with_name("sub-min-balance")
+			.long("sub-min-balance")
+			.value_name("BALANCE")
+			.help("Transactions are not submitted if signer balance is below this threshold")
+			.takes_value(true))
+		.arg(Arg::with_name("signer")
+			.long("signer")
+			.value_name("SURI")
+			.help("Secret URI of transactions signer")
+			.takes_value(true))
+		.arg(Arg::with_name("signer-mnemonic-file")
+			.long("signer-mnemonic-file")
+			.value_name("PATH")
+			.help("Path to file with BIP39 mnemonic phrase of transactions signer")
+			.takes_value(true)
+			.conflicts_with("signer"))
+		.arg(Arg::with_name("signer-keystore-file")
+			.long("signer-keystore-file")
+			.value_name("PATH")
+			.help("Path to Substrate keystore file of transactions signer")
+			.takes_value(true)
+			.conflicts_with_all(&["signer", "signer-mnemonic-file"]))
+		.arg(Arg::with_name("signer-password-file")
+			.long("signer-password-file")
+			.value_name("PATH")
+			.help("Path to file with password of transactions signer secret")
+			.takes_value(true))
+		.arg(Arg::with_name("signer-scheme")
+			.long("signer-scheme")
+			.value_name("SCHEME")
+			.help("Signature scheme of transactions signer")
+			.possible_values(&["sr25519", "ed25519", "ecdsa"])
+			.takes_value(true))
+		.arg(Arg::with_name("self-secret")
+			.long("self-secret")
+			.value_name("HEX")
+			.help("Hex-encoded secret of the key server key pair")
+			.takes_value(true))
+		.arg(Arg::with_name("self-secret-file")
+			.long("self-secret-file")
+			.value_name("PATH")
+			.help("Path to file with hex-encoded secret of the key server key pair")
+			.takes_value(true)
+			.conflicts_with("self-secret"))
+		.arg(Arg::with_name("self-keystore")
+			.long("self-keystore")
+			.value_name("PATH")
+			.help("Path to the keystore file with the key server key pair")
+			.takes_value(true)
+			.conflicts_with_all(&["self-secret", "self-secret-file"])
+			.requires("self-keystore-password-file"))
+		.arg(Arg::with_name("self-keystore-password-file")
+			.long("self-keystore-password-file")
+			.value_name("PATH")
+			.help("Path to file with password of the key server keystore file")
+			.takes_value(true)
+			.requires("self-keystore"))
+		.arg(Arg::with_name("net-address")
+			.long("net-address")
+			.value_name("ADDRESS")
+			.help("Network interface the key server listens on")
+			.takes_value(true))
+		.arg(Arg::with_name("net-port")
+			.long("net-port")
+			.value_name("PORT")
+			.help("Network port the key server listens on")
+			.takes_value(true))
+		.arg(Arg::with_name("admin")
+			.long("admin")
+			.value_name("ADDRESS")
+			.help("Address of the key server set administrator")
+			.takes_value(true))
+		.arg(Arg::with_name("migration-state-file")
+			.long("migration-state-file")
+			.value_name("PATH")
+			.help("Path to the file where last sent migration transactions are stored")
+			.takes_value(true))
+		.arg(Arg::with_name("db-path")
+			.long("db-path")
+			.value_name("PATH")
+			.help("Path to the key storage database (keys are stored in memory if not specified)")
+			.takes_value(true))
+		.arg(Arg::with_name("db-import-file")
+			.long("db-import-file")
+			.value_name("PATH")
+			.help("Path to the JSON file with key shares that are imported when key storage database is created")
+			.takes_value(true))
+		.arg(Arg::with_name("db-sealing-key-file")
+			.long("db-sealing-key-file")
+			.value_name("PATH")
+			.help("Path to file with hex-encoded key that is used to encrypt stored key shares")
+			.takes_value(true))
+		.arg(Arg::with_name("db-sealing-passphrase-file")
+			.long("db-sealing-passphrase-file")
+			.value_name("PATH")
+			.help("Path to file with passphrase that is used to encrypt stored key shares")
+			.takes_value(true)
+			.conflicts_with("db-sealing-key-file"))
+		.arg(Arg::with_name("max-active-sessions")
+			.long("max-active-sessions")
+			.value_name("NUMBER")
+			.help("Max number of concurrently active service sessions (0 for unlimited)")
+			.takes_value(true))
+		.arg(Arg::with_name("pending-restart-interval")
+			.long("pending-restart-interval")
+			.value_name("SECONDS")
+			.help("Interval between pending service tasks restarts (0 to disable)")
+			.takes_value(true))
+		.arg(Arg::with_name("checkpoint-file")
+			.long("checkpoint-file")
+			.value_name("PATH")
+			.help("Path to the file where last processed block is stored")
+			.takes_value(true))
+		.arg(Arg::with_name("max-catch-up-blocks")
+			.long("max-catch-up-blocks")
+			.value_name("BLOCKS")
+			.help("Max number of finalized blocks that are replayed after restart")
+			.takes_value(true))
+		.arg(Arg::with_name("admin-rpc-address")
+			.long("admin-rpc-address")
+			.value_name("ADDRESS")
+			.help("Admin endpoint network interface")
+			.takes_value(true))
+		.arg(Arg::with_name("admin-rpc-port")
+			.long("admin-rpc-port")
+			.value_name("PORT")
+			.help("Admin endpoint network port. Admin endpoint is disabled if not specified")
+			.takes_value(true))
+		.subcommand(SubCommand::with_name("keystore")
+			.about("Manage key server keystore files")
+			.setting(AppSettings::SubcommandRequiredElseHelp)
+			.subcommand(SubCommand::with_name("generate")
+				.about("Generate new keystore file")
+				.arg(Arg::with_name("path")
+					.long("path")
+					.value_name("PATH")
+					.help("Path of the new keystore file")
+					.takes_value(true)
+					.required(true))
+				.arg(Arg::with_name("password-file")
+					.long("password-file")
+					.value_name("PATH")
+					.help("Path to file with keystore password")
+					.takes_value(true)
+					.required(true))
+				.arg(Arg::with_name("secret-file")
+					.long("secret-file")
+					.value_name("PATH")
+					.help("Path to file with hex-encoded secret to import (random secret is generated if not specified)")
+					.takes_value(true)))
+			.subcommand(SubCommand::with_name("inspect")
+				.about("Print keystore file information")
+				.arg(Arg::with_name("path")
+					.long("path")
+					.value_name("PATH")
+					.help("Path of the keystore file")
+					.takes_value(true)
+					.required(true))
+				.arg(Arg::with_name("password-file")
+					.long("password-file")
+					.value_name("PATH")
+					.help("Path to file with keystore password (keystore is decrypted if specified)")
+					.takes_value(true))))
+}
+
+/// Read and parse configuration file.
+fn read_configuration_file(path: &Path) -> Result<ConfigurationFile, Error> {
+	let contents = std::fs::read_to_string(path).map_err(|error| Error::Io(path.into(), error))?;
+	toml::from_str(&contents).map_err(|error| Error::Toml(path.into(), error))
+}
+
+/// Merge command line arguments with configuration file and validate the result.
+fn build_configuration(matches: &ArgMatches, file: ConfigurationFile) -> Result<Configuration, Error> {
+	let substrate = file.substrate.unwrap_or_default();
+	let signer = file.signer.unwrap_or_default();
+	let key_server = file.key_server.unwrap_or_default();
+	let key_storage = file.key_storage.unwrap_or_default();
+	let service = file.service.unwrap_or_default();
+	let admin = file.admin.unwrap_or_default();
+
+	let key_pair = read_key_server_key_pair(
+		matches,
+		key_server.secret,
+		key_server.secret_file,
+		key_server.keystore_file,
+		key_server.keystore_password_file,
+	)?;
+
+	Ok(Configuration {
+		substrate: build_substrate_configuration(matches, substrate)?,
+		signer: SignerConfiguration {
+			signer: read_signer(matches, signer)?,
+		},
+		key_server: KeyServerConfiguration {
+			key_pair,
+			listen_address: matches.value_of("net-address").map(Into::into)
+				.or(key_server.listen_address)
+				.unwrap_or_else(|| DEFAULT_LISTEN_ADDRESS.into()),
+			listen_port: parse_arg(matches, "net-port")?
+				.or(key_server.listen_port)
+				.unwrap_or(DEFAULT_LISTEN_PORT),
+			admin_address: matches.value_of("admin").map(Into::into)
+				.or(key_server.admin_address)
+				.map(|admin_address| parse_address(&admin_address))
+				.transpose()?,
+			migration_state_file: matches.value_of("migration-state-file").map(Into::into)
+				.or(key_server.migration_state_file),
+		},
+		key_storage: build_key_storage_configuration(matches, key_storage)?,
+		service: ServiceConfiguration {
+			max_active_sessions: Some(
+				parse_arg(matches, "max-active-sessions")?
+					.or(service.max_active_sessions)
+					.unwrap_or(DEFAULT_MAX_ACTIVE_SESSIONS)
+			).filter(|max_active_sessions| *max_active_sessions != 0),
+			pending_restart_interval: Some(
+				parse_arg(matches, "pending-restart-interval")?
+					.or(service.pending_restart_interval)
+					.unwrap_or(DEFAULT_PENDING_RESTART_INTERVAL)
+			).filter(|interval| *interval != 0).map(Duration::from_secs),
+			checkpoint_file: matches.value_of("checkpoint-file").map(Into::into)
+				.or(service.checkpoint_file),
+			max_catch_up_blocks: parse_arg(matches, "max-catch-up-blocks")?
+				.or(service.max_catch_up_blocks)
+				.unwrap_or(DEFAULT_MAX_CATCH_UP_BLOCKS),
+		},
+		admin: AdminConfiguration {
+			listen_address: parse_arg(matches, "admin-rpc-port")?
+				.or(admin.listen_port)
+				.map(|port: u16| format!(
+					"{}:{}",
+					matches.value_of("admin-rpc-address").map(Into::into)
+						.or(admin.listen_address)
+						.unwrap_or_else(|| DEFAULT_ADMIN_LISTEN_ADDRESS.into()),
+					port,
+				)),
+		},
+	})
+}
+
+/// Build Substrate node connection configuration.
+fn build_substrate_configuration(
+	matches: &ArgMatches,
+	substrate: SubstrateSection,
+) -> Result<SubstrateConfiguration, Error> {
+	let transaction_era = parse_arg(matches, "sub-transaction-era")?
+		.or(substrate.transaction_era)
+		.or(Some(DEFAULT_TRANSACTION_ERA))
+		.filter(|era| *era != 0);
+	if let Some(era) = transaction_era {
+		if era < MIN_TRANSACTION_ERA || era > MAX_TRANSACTION_ERA {
+			return Err(Error::InvalidOption(
+				"substrate.transaction_era",
+				format!("must be 0 or in range {}..={}", MIN_TRANSACTION_ERA, MAX_TRANSACTION_ERA),
+			));
+		}
+	}
+	let fee_policy = build_fee_policy(matches, &substrate)?;
+	let best_block_confirmations = parse_arg(matches, "sub-best-block-confirmations")?
+		.or(substrate.best_block_confirmations);
+	if let Some(endpoints) = matches.values_of("sub-endpoint") {
+		return Ok(SubstrateConfiguration {
+			endpoints: endpoints.map(Into::into).collect(),
+			transaction_era,
+			fee_policy,
+			best_block_confirmations,
+		});
+	}
+
+	let host = matches.value_of("sub-host").map(Into::into).or(substrate.host);
+	let port = parse_arg(matches, "sub-port")?.or(substrate.port);
+	match substrate.endpoints {
+		Some(_) if host.is_some() || port.is_some() => Err(Error::InvalidOption(
+			"substrate.endpoints",
+			"endpoints can not be specified together with host and port".into(),
+		)),
+		Some(ref endpoints) if endpoints.is_empty() => Err(Error::MissingOption("substrate.endpoints")),
+		Some(endpoints) => Ok(SubstrateConfiguration {
+			endpoints,
+			transaction_era,
+			fee_policy,
+			best_block_confirmations,
+		}),
+		None => Ok(SubstrateConfiguration {
+			endpoints: vec![format!(
+				"{}:{}",
+				host.unwrap_or_else(|| DEFAULT_SUBSTRATE_HOST.into()),
+				port.unwrap_or(DEFAULT_SUBSTRATE_PORT),
+			)],
+			transaction_era,
+			fee_policy,
+			best_block_confirmations,
+		}),
+	}
+}
+
+/// Build transactions fee policy.
+fn build_fee_policy(matches: &ArgMatches, substrate: &SubstrateSection) -> Result<FeePolicy, Error> {
+	let tip = parse_arg(matches, "sub-tip")?.or(parse_balance_value("substrate.tip", substrate.tip.as_ref())?);
+	let tip_fee_percent = parse_arg(matches, "sub-tip-fee-percent")?.or(substrate.tip_fee_percent);
+	let tip = match (tip, tip_fee_percent) {
+		(Some(_), Some(_)) => return Err(Error::InvalidOption(
+			"substrate.tip_fee_percent",
+			"tip_fee_percent can not be specified together with tip".into(),
+		)),
+		(Some(tip), None) => TipPolicy::Fixed(tip),
+		(None, Some(percent)) => TipPolicy::FeePercent(percent),
+		(None, None) => FeePolicy::default().tip,
+	};
+
+	Ok(FeePolicy {
+		tip,
+		tip_escalation_percent: parse_arg(matches, "sub-tip-escalation-percent")?
+			.or(substrate.tip_escalation_percent)
+			.unwrap_or_default(),
+		max_tip: parse_arg(matches, "sub-max-tip")?
+			.or(parse_balance_value("substrate.max_tip", substrate.max_tip.as_ref())?),
+		min_balance: parse_arg(matches, "sub-min-balance")?
+			.or(parse_balance_value("substrate.min_balance", substrate.min_balance.as_ref())?)
+			.unwrap_or_default(),
+	})
+}
+
+/// Read transactions signer from one of configured sources.
+fn read_signer(matches: &ArgMatches, signer: SignerSection) -> Result<Signer, Error> {
+	let scheme = match matches.value_of("signer-scheme").map(Into::into).or(signer.scheme) {
+		Some(scheme) => scheme.parse::<SignatureScheme>()
+			.map_err(|error| Error::InvalidOption("signer.scheme", error))?,
+		None => SignatureScheme::Sr25519,
+	};
+	let source = if let Some(uri) = matches.value_of("signer") {
+		SignerSource::Uri(uri.into())
+	} else if let Some(path) = matches.value_of("signer-mnemonic-file") {
+		SignerSource::MnemonicFile(path.into())
+	} else if let Some(path) = matches.value_of("signer-keystore-file") {
+		SignerSource::KeystoreFile(path.into())
+	} else {
+		match (signer.uri, signer.mnemonic_file, signer.keystore_file) {
+			(Some(uri), None, None) => SignerSource::Uri(uri),
+			(None, Some(path), None) => SignerSource::MnemonicFile(path),
+			(None, None, Some(path)) => SignerSource::KeystoreFile(path),
+			(None, None, None) => return Err(Error::MissingOption("signer.uri")),
+			_ => return Err(Error::InvalidOption(
+				"signer.uri",
+				"only one of uri, mnemonic_file and keystore_file can be specified".into(),
+			)),
+		}
+	};
+	let password_file = matches.value_of("signer-password-file").map(PathBuf::from)
+		.or(signer.password_file);
+
+	Signer::load(scheme, source, password_file.as_ref().map(|path| path.as_path()))
+		.map_err(Error::Signer)
+}
+
+/// Read key server key pair from one of configured sources.
+fn read_key_server_key_pair(
+	matches: &ArgMatches,
+	secret: Option<String>,
+	secret_file: Option<PathBuf>,
+	keystore_file: Option<PathBuf>,
+	keystore_password_file: Option<PathBuf>,
+) -> Result<KeyPair, Error> {
+	if let Some(secret) = matches.value_of("self-secret") {
+		return parse_key_pair(secret);
+	}
+	if let Some(path) = matches.value_of("self-secret-file") {
+		return parse_key_pair(&read_secret_file(Path::new(path))?);
+	}
+	if let (Some(path), Some(password_file)) = (matches.value_of("self-keystore"), matches.value_of("self-keystore-password-file")) {
+		return keystore::load_key_pair(Path::new(path), Path::new(password_file)).map_err(Error::Keystore);
+	}
+
+	match (secret, secret_file, keystore_file, keystore_password_file) {
+		(Some(secret), None, None, None) => parse_key_pair(&secret),
+		(None, Some(path), None, None) => parse_key_pair(&read_secret_file(&path)?),
+		(None, None, Some(path), Some(password_file)) => keystore::load_key_pair(&path, &password_file)
+			.map_err(Error::Keystore),
+		(None, None, Some(_), None) => Err(Error::MissingOption("key_server.keystore_password_file")),
+		(None, None, None, _) => Err(Error::MissingOption("key_server.secret")),
+		_ => Err(Error::InvalidOption(
+			"key_server.secret",
+			"only one of secret, secret_file and keystore_file can be specified".into(),
+		)),
+	}
+}
+
+/// Build key storage configuration.
+fn build_key_storage_configuration(
+	matches: &ArgMatches,
+	key_storage: KeyStorageSection,
+) -> Result<KeyStorageConfiguration, Error> {
+	let path = matches.value_of("db-path").map(Into::into).or(key_storage.path);
+	let sealing_key = match (matches.value_of("db-sealing-key-file"), matches.value_of("db-sealing-passphrase-file")) {
+		(Some(path), _) => Some(SealingKeySource::KeyFile(path.into())),
+		(None, Some(path)) => Some(SealingKeySource::PassphraseFile(path.into())),
+		(None, None) => match (key_storage.sealing_key_file, key_storage.sealing_passphrase_file) {
+			(Some(_), Some(_)) => return Err(Error::InvalidOption(
+				"key_storage.sealing_key_file",
+				"only one of sealing_key_file and sealing_passphrase_file can be specified".into(),
+			)),
+			(Some(path), None) => Some(SealingKeySource::KeyFile(path)),
+			(None, Some(path)) => Some(SealingKeySource::PassphraseFile(path)),
+			(None, None) => None,
+		},
+	};
+	let previous_sealing_keys = key_storage.previous_sealing_key_files.into_iter()
+		.map(SealingKeySource::KeyFile)
+		.chain(key_storage.previous_sealing_passphrase_files.into_iter().map(SealingKeySource::PassphraseFile))
+		.collect::<Vec<_>>();
+
+	if sealing_key.is_some() && path.is_none() {
+		return Err(Error::InvalidOption(
+			"key_storage.sealing_key_file",
+			"sealing key requires key storage database path".into(),
+		));
+	}
+	if !previous_sealing_keys.is_empty() && sealing_key.is_none() {
+		return Err(Error::MissingOption("key_storage.sealing_key_file"));
+	}
+	let import_file = matches.value_of("db-import-file").map(Into::into).or(key_storage.import_file);
+	if import_file.is_some() && path.is_none() {
+		return Err(Error::InvalidOption(
+			"key_storage.import_file",
+			"key shares import requires key storage database path".into(),
+		));
+	}
+
+	Ok(KeyStorageConfiguration {
+		path,
+		sealing_key,
+		previous_sealing_keys,
+		import_file,
+	})
+}
+
+/// Read required path argument.
+fn required_path(matches: &ArgMatches, name: &'static str) -> Result<PathBuf, Error> {
+	matches.value_of(name).map(Into::into).ok_or(Error::MissingOption(name))
+}
+
+/// Parse optional command line argument.
+fn parse_arg<T>(matches: &ArgMatches, name: &'static str) -> Result<Option<T>, Error>
+	where
+		T: std::str::FromStr,
+		T::Err: std::fmt::Display,
+{
+	matches.value_of(name)
+		.map(|value| value.parse().map_err(|error: T::Err| Error::InvalidOption(name, error.to_string())))
+		.transpose()
+}
+
+/// Parse balance from configuration file.
+fn parse_balance_value(
+	name: &'static str,
+	value: Option<&BalanceValue>,
+) -> Result<Option<crate::runtime::Balance>, Error> {
+	match value {
+		Some(BalanceValue::Integer(value)) => Ok(Some((*value).into())),
+		Some(BalanceValue::String(value)) => value.parse()
+			.map(Some)
+			.map_err(|error: std::num::ParseIntError| Error::InvalidOption(name, error.to_string())),
+		None => Ok(None),
+	}
+}
+
+/// Read hex-encoded secret from file.
+fn read_secret_file(path: &Path) -> Result<String, Error> {
+	std::fs::read_to_string(path)
+		.map(|secret| secret.trim().to_owned())
+		.map_err(|error| Error::Io(path.into(), error))
+}
+
+/// Parse hex-encoded key pair secret.
+fn parse_key_pair(secret: &str) -> Result<KeyPair, Error> {
+	let secret = decode_hex::<[u8; 32]>(secret, "key_server.secret")?;
+	KeyPair::from_secret(secret.into())
+		.map_err(|error| Error::InvalidOption("key_server.secret", error.to_string()))
+}
+
+/// Parse hex-encoded address.
+fn parse_address(address: &str) -> Result<Address, Error> {
+	decode_hex::<[u8; 20]>(address, "key_server.admin_address").map(Into::into)
+}
+
+/// Decode hex string (with optional 0x prefix) into fixed-size array.
+fn decode_hex<T: AsMut<[u8]> + Default>(value: &str, option: &'static str) -> Result<T, Error> {
+	let value = value.trim_start_matches("0x");
+	let mut result = T::default();
+	hex::decode_to_slice(value, result.as_mut())
+		.map_err(|error| Error::InvalidOption(option, error.to_string()))?;
+	Ok(result)
+}
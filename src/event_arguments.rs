@@ -0,0 +1,228 @@
+use codec::{Compact, Decode};
+
+/// Definitions of named types that are used in arguments of runtime events. Every type
+/// is defined in terms of primitive types, generic containers, tuples, arrays and other
+/// named types.
+const NAMED_TYPES: &[(&str, &str)] = &[
+	("AccountId", "[u8;32]"),
+	("AccountIndex", "u32"),
+	("AuthorityId", "[u8;32]"),
+	("AuthorityIndex", "u32"),
+	("AuthorityList", "Vec<(AuthorityId,AuthorityWeight)>"),
+	("AuthorityWeight", "u64"),
+	("Balance", "u128"),
+	("BalanceOf", "Balance"),
+	("BlockNumber", "u32"),
+	("CallHash", "[u8;32]"),
+	("EraIndex", "u32"),
+	("Exposure", "(Compact<Balance>,Compact<Balance>,Vec<IndividualExposure>)"),
+	("H160", "[u8;20]"),
+	("H256", "[u8;32]"),
+	("Hash", "[u8;32]"),
+	("IdentificationTuple", "(AccountId,Exposure)"),
+	("Index", "u32"),
+	("IndividualExposure", "(AccountId,Compact<Balance>)"),
+	("Kind", "[u8;16]"),
+	("MemberCount", "u32"),
+	("Moment", "u64"),
+	("OpaqueTimeSlot", "Vec<u8>"),
+	("PropIndex", "u32"),
+	("ProposalIndex", "u32"),
+	("ReferendumIndex", "u32"),
+	("SessionIndex", "u32"),
+	("Timepoint", "(BlockNumber,u32)"),
+	("VoteThreshold", "u8"),
+];
+
+/// All possible errors that can occur when skipping event arguments.
+#[derive(Debug)]
+pub enum Error {
+	/// Argument type is not known.
+	UnknownType(String),
+	/// Failed to decode argument.
+	Decode(codec::Error),
+}
+
+/// Skip SCALE-encoded value of given type, as it is declared in runtime metadata.
+pub fn skip(ty: &str, input: &mut &[u8]) -> Result<(), Error> {
+	let ty: String = ty.chars().filter(|c| !c.is_whitespace()).collect();
+	skip_normalized(&ty, input)
+}
+
+/// Skip SCALE-encoded value of given type, which has no whitespaces in its name.
+fn skip_normalized(ty: &str, input: &mut &[u8]) -> Result<(), Error> {
+	if ty.starts_with('(') && ty.ends_with(')') {
+		return split_top_level(&ty[1..ty.len() - 1], ',')
+			.into_iter()
+			.filter(|item| !item.is_empty())
+			.try_for_each(|item| skip_normalized(item, input));
+	}
+	if ty.starts_with('[') && ty.ends_with(']') {
+		let mut parts = ty[1..ty.len() - 1].rsplitn(2, ';');
+		let len = parts.next().and_then(|len| len.parse::<usize>().ok());
+		return match (parts.next(), len) {
+			(Some(item), Some(len)) => skip_items(item, len, input),
+			_ => Err(Error::UnknownType(ty.into())),
+		};
+	}
+
+	let ty = last_path_segment(ty);
+	let (name, arguments) = match (ty.find('<'), ty.ends_with('>')) {
+		(Some(position), true) => (&ty[..position], split_top_level(&ty[position + 1..ty.len() - 1], ',')),
+		_ => (ty, Vec::new()),
+	};
+	match (name, arguments.as_slice()) {
+		("bool", []) | ("u8", []) | ("i8", []) => take(input, 1),
+		("u16", []) | ("i16", []) => take(input, 2),
+		("u32", []) | ("i32", []) => take(input, 4),
+		("u64", []) | ("i64", []) => take(input, 8),
+		("u128", []) | ("i128", []) => take(input, 16),
+		("PhantomData", _) => Ok(()),
+		("Box", [item]) => skip_normalized(item, input),
+		("Compact", [_]) => Compact::<u128>::decode(input).map(|_| ()).map_err(Error::Decode),
+		("Vec", [item]) | ("VecDeque", [item]) | ("BTreeSet", [item]) => {
+			let len = Compact::<u32>::decode(input).map_err(Error::Decode)?.0;
+			skip_items(item, len as usize, input)
+		},
+		("BTreeMap", [key, value]) => {
+			let len = Compact::<u32>::decode(input).map_err(Error::Decode)?.0;
+			(0..len).try_for_each(|_| {
+				skip_normalized(key, input)?;
+				skip_normalized(value, input)
+			})
+		},
+		("Option", [item]) => match u8::decode(input).map_err(Error::Decode)? {
+			0 => Ok(()),
+			1 => skip_normalized(item, input),
+			_ => Err(Error::Decode("invalid Option discriminant".into())),
+		},
+		("Result", [ok, err]) => match u8::decode(input).map_err(Error::Decode)? {
+			0 => skip_normalized(ok, input),
+			1 => skip_normalized(err, input),
+			_ => Err(Error::Decode("invalid Result discriminant".into())),
+		},
+		// generic parameters of named types (like `BalanceOf<T>`) do not affect encoding
+		_ => match NAMED_TYPES.iter().find(|(named_type, _)| *named_type == name) {
+			Some((_, definition)) => skip_normalized(definition, input),
+			None => Err(Error::UnknownType(ty.into())),
+		},
+	}
+}
+
+/// Skip given number of SCALE-encoded values of given type.
+fn skip_items(item: &str, len: usize, input: &mut &[u8]) -> Result<(), Error> {
+	match item {
+		"u8" | "i8" => take(input, len),
+		_ => (0..len).try_for_each(|_| skip_normalized(item, input)),
+	}
+}
+
+/// Skip given number of bytes.
+fn take(input: &mut &[u8], len: usize) -> Result<(), Error> {
+	if input.len() < len {
+		return Err(Error::Decode("not enough data to skip event argument".into()));
+	}
+
+	*input = &input[len..];
+	Ok(())
+}
+
+/// Returns last segment of the type path: `Balance` for `<T as Trait>::Balance`.
+fn last_path_segment(ty: &str) -> &str {
+	let mut depth = 0;
+	let mut segment_start = 0;
+	let bytes = ty.as_bytes();
+	for (index, byte) in bytes.iter().enumerate() {
+		match byte {
+			b'<' | b'(' | b'[' => depth += 1,
+			b'>' | b')' | b']' => depth -= 1,
+			b':' if depth == 0 && bytes.get(index + 1) == Some(&b':') => segment_start = index + 2,
+			_ => (),
+		}
+	}
+	&ty[segment_start..]
+}
+
+/// Split type list by separator, ignoring separators of nested types.
+fn split_top_level(list: &str, separator: char) -> Vec<&str> {
+	let mut depth = 0;
+	let mut items = Vec::new();
+	let mut item_start = 0;
+	for (index, character) in list.char_indices() {
+		match character {
+			'<' | '(' | '[' => depth += 1,
+			'>' | ')' | ']' => depth -= 1,
+			_ if character == separator && depth == 0 => {
+				items.push(&list[item_start..index]);
+				item_start = index + 1;
+			},
+			_ => (),
+		}
+	}
+	if item_start < list.len() {
+		items.push(&list[item_start..]);
+	}
+	items
+}
+
+#[cfg(test)]
+mod tests {
+	use codec::Encode;
+	use super::*;
+
+	fn skip_all(ty: &str, encoded: &[u8]) -> Result<(), Error> {
+		let input = &mut &encoded[..];
+		skip(ty, input)?;
+		assert!(input.is_empty(), "{} bytes left after skipping {}", input.len(), ty);
+		Ok(())
+	}
+
+	#[test]
+	fn primitive_and_container_types_are_skipped() {
+		skip_all("bool", &true.encode()).unwrap();
+		skip_all("u64", &42u64.encode()).unwrap();
+		skip_all("Vec<u8>", &vec![1u8, 2, 3].encode()).unwrap();
+		skip_all("Vec<(u32, u128)>", &vec![(1u32, 2u128), (3, 4)].encode()).unwrap();
+		skip_all("Option<u32>", &Some(5u32).encode()).unwrap();
+		skip_all("Option<u32>", &None::<u32>.encode()).unwrap();
+		skip_all("Compact<u128>", &Compact(1_000_000u128).encode()).unwrap();
+		skip_all("[u8; 16]", &[7u8; 16].encode()).unwrap();
+		skip_all("()", &[]).unwrap();
+	}
+
+	#[test]
+	fn named_types_are_skipped() {
+		skip_all("T::AccountId", &[1u8; 32]).unwrap();
+		skip_all("<T as Trait>::Balance", &100u128.encode()).unwrap();
+		skip_all("BalanceOf<T>", &100u128.encode()).unwrap();
+		skip_all("AuthorityList", &vec![([1u8; 32], 1u64)].encode()).unwrap();
+		skip_all(
+			"IdentificationTuple",
+			&([1u8; 32], Compact(10u128), Compact(5u128), vec![([2u8; 32], Compact(5u128))]).encode(),
+		).unwrap();
+	}
+
+	#[test]
+	fn unknown_types_are_not_skipped() {
+		match skip("T::Unknown", &mut &[0u8; 32][..]) {
+			Err(Error::UnknownType(ref ty)) if ty == "Unknown" => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match skip("Vec<Unknown>", &mut &vec![1u8].encode()[..]) {
+			Err(Error::UnknownType(ref ty)) if ty == "Unknown" => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn truncated_values_are_not_skipped() {
+		match skip("u128", &mut &[0u8; 15][..]) {
+			Err(Error::Decode(_)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match skip("Option<u32>", &mut &[2u8, 0, 0, 0, 0][..]) {
+			Err(Error::Decode(_)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+}
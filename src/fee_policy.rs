@@ -0,0 +1,118 @@
+use crate::runtime::Balance;
+
+/// Policy of computing transaction tip.
+#[derive(Clone, Copy, Debug)]
+pub enum TipPolicy {
+	/// Fixed tip.
+	Fixed(Balance),
+	/// Tip is a percentage of the estimated transaction fee.
+	FeePercent(u32),
+}
+
+/// Transactions fee policy.
+#[derive(Clone, Copy, Debug)]
+pub struct FeePolicy {
+	/// Policy of computing initial transaction tip.
+	pub tip: TipPolicy,
+	/// Percent by which tip is increased every time transaction is resubmitted.
+	pub tip_escalation_percent: u32,
+	/// Tip is never increased above this value.
+	pub max_tip: Option<Balance>,
+	/// Transactions are not submitted if signer balance is below this threshold.
+	pub min_balance: Balance,
+}
+
+impl Default for FeePolicy {
+	fn default() -> Self {
+		FeePolicy {
+			tip: TipPolicy::Fixed(0),
+			tip_escalation_percent: 0,
+			max_tip: None,
+			min_balance: 0,
+		}
+	}
+}
+
+impl FeePolicy {
+	/// Returns true if transaction fee estimation is required to compute tip.
+	pub fn requires_fee_estimation(&self) -> bool {
+		match self.tip {
+			TipPolicy::Fixed(_) => false,
+			TipPolicy::FeePercent(_) => true,
+		}
+	}
+
+	/// Compute tip for given (0-based) submission attempt.
+	pub fn tip(&self, estimated_fee: Balance, attempt: u32) -> Balance {
+		let max_tip = self.max_tip.unwrap_or(Balance::max_value());
+		let mut tip = match self.tip {
+			TipPolicy::Fixed(tip) => tip,
+			TipPolicy::FeePercent(percent) => percent_of(estimated_fee, percent),
+		};
+		for _ in 0..attempt {
+			if tip >= max_tip {
+				break;
+			}
+			tip = tip.saturating_add(percent_of(tip, self.tip_escalation_percent));
+		}
+		tip.min(max_tip)
+	}
+}
+
+/// Compute given percent of the value.
+fn percent_of(value: Balance, percent: u32) -> Balance {
+	value.saturating_mul(percent as Balance) / 100
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn policy(tip: TipPolicy, tip_escalation_percent: u32, max_tip: Option<Balance>) -> FeePolicy {
+		FeePolicy {
+			tip,
+			tip_escalation_percent,
+			max_tip,
+			min_balance: 0,
+		}
+	}
+
+	#[test]
+	fn base_tip_is_computed() {
+		assert_eq!(policy(TipPolicy::Fixed(100), 0, None).tip(1_000, 0), 100);
+		assert_eq!(policy(TipPolicy::FeePercent(10), 0, None).tip(1_000, 0), 100);
+		assert_eq!(policy(TipPolicy::FeePercent(250), 0, None).tip(1_000, 0), 2_500);
+		assert_eq!(policy(TipPolicy::FeePercent(0), 0, None).tip(1_000, 0), 0);
+	}
+
+	#[test]
+	fn tip_is_escalated_on_resubmission() {
+		let policy = policy(TipPolicy::Fixed(100), 50, None);
+		assert_eq!(policy.tip(0, 0), 100);
+		assert_eq!(policy.tip(0, 1), 150);
+		assert_eq!(policy.tip(0, 2), 225);
+	}
+
+	#[test]
+	fn tip_is_not_escalated_without_escalation_percent() {
+		assert_eq!(policy(TipPolicy::Fixed(100), 0, None).tip(0, 5), 100);
+	}
+
+	#[test]
+	fn tip_is_capped() {
+		let policy = policy(TipPolicy::Fixed(100), 50, Some(200));
+		assert_eq!(policy.tip(0, 1), 150);
+		assert_eq!(policy.tip(0, 2), 200);
+		assert_eq!(policy.tip(0, 100), 200);
+
+		assert_eq!(self::policy(TipPolicy::FeePercent(50), 0, Some(200)).tip(1_000, 0), 200);
+	}
+
+	#[test]
+	fn tip_computation_saturates() {
+		let max = Balance::max_value();
+		assert_eq!(policy(TipPolicy::FeePercent(200), 0, None).tip(max, 0), max / 100);
+		assert_eq!(policy(TipPolicy::Fixed(max / 2), 100, None).tip(0, 1), max / 2 + max / 100);
+		assert_eq!(policy(TipPolicy::Fixed(max), 100, None).tip(0, 10), max);
+	}
+}
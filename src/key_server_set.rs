@@ -1,33 +1,55 @@
 use std::{
-	collections::BTreeMap,
+	collections::{BTreeMap, BTreeSet},
 	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::Arc,
 };
-use log::error;
-use parking_lot::RwLock;
+use codec::Encode;
+use log::{error, info, warn};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use sp_core::H256;
 use parity_secretstore_primitives::{
 	KeyServerId,
-	key_server_set::{KeyServerSet, KeyServerSetSnapshot, MigrationId},
+	key_server_set::{KeyServerSet, KeyServerSetMigration, KeyServerSetSnapshot, MigrationId},
 	error::Error,
 };
-use crate::substrate_client::Client;
+use crate::{
+	address_resolver::AddressResolver,
+	async_bridge::AsyncBridge,
+	runtime::{DefaultRuntime, SecretStoreRuntime},
+	substrate_client::{Client, TransactionOutcome},
+};
 
-/// Number of blocks before the same-migration transaction (be it start or confirmation) will be retried.
+/// Number of blocks before the same-migration transaction (be it start or confirmation) will be retried,
+/// if we have failed to watch its outcome.
 const TRANSACTION_RETRY_INTERVAL_BLOCKS: u32 = 30;
+/// Retry interval is doubled after every failed transaction, but no more than this number of times.
+const MAX_RETRY_BACKOFF_EXPONENT: u32 = 6;
 
 pub struct OnChainKeyServerSet {
 	client: Client,
+	bridge: AsyncBridge,
 	self_id: KeyServerId,
-	data: RwLock<OnChainKeyServerSetData>,
+	address_resolver: AddressResolver,
+	data: Arc<RwLock<OnChainKeyServerSetData>>,
+	/// Lock that serializes writes to the migration state file.
+	persist_lock: Arc<Mutex<()>>,
 }
 
 struct OnChainKeyServerSetData {
 	best_block: Option<(u32, H256)>,
 	best_block_snapshot: KeyServerSetSnapshot<SocketAddr>,
+	/// Key servers that have confirmed active migration at the best block.
+	migration_confirmations: BTreeSet<KeyServerId>,
 	/// Previous start migration transaction (if has been sent).
 	start_migration_tx: Option<PreviousMigrationTransaction>,
 	/// Previous confirm migration transaction (if has been sent).
 	confirm_migration_tx: Option<PreviousMigrationTransaction>,
+	/// Migration that operator has decided to abstain from.
+	abstained_migration: Option<MigrationId>,
+	/// Path to the file where previous migration transactions are stored.
+	migration_state_file: Option<PathBuf>,
 }
 
 struct PreviousMigrationTransaction {
@@ -35,28 +57,355 @@ struct PreviousMigrationTransaction {
 	migration_id: MigrationId,
 	/// Best block when transaction has been sent.
 	block: (u32, H256),
+	/// True if transaction has been successfully dispatched in finalized block.
+	is_finalized: bool,
+	/// Number of failed attempts to submit this transaction.
+	failures: u32,
+}
+
+/// Previous migration transactions, as they are stored in the migration state file.
+#[derive(Default, Serialize, Deserialize)]
+struct PersistentMigrationTransactions {
+	/// Previous start migration transaction.
+	start_migration_tx: Option<PersistentMigrationTransaction>,
+	/// Previous confirm migration transaction.
+	confirm_migration_tx: Option<PersistentMigrationTransaction>,
+}
+
+/// Previous migration transaction, as it is stored in the migration state file.
+#[derive(Serialize, Deserialize)]
+struct PersistentMigrationTransaction {
+	/// Hex-encoded migration ID.
+	migration_id: String,
+	/// Number of best block when transaction has been sent.
+	block_number: u32,
+	/// Hex-encoded hash of best block when transaction has been sent.
+	block_hash: String,
+	/// True if transaction has been successfully dispatched in finalized block.
+	is_finalized: bool,
+	/// Number of failed attempts to submit this transaction.
+	#[serde(default)]
+	failures: u32,
+}
+
+/// Decision on migration transaction submission.
+enum SubmissionDecision {
+	/// Submit transaction for given reason.
+	Submit(&'static str),
+	/// Do not submit transaction for given reason.
+	Skip(&'static str),
+}
+
+/// Current migration state, as seen by this key server.
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+	/// Number of the best block.
+	pub best_block: Option<u32>,
+	/// Migration ID (if migration is active).
+	pub migration_id: Option<String>,
+	/// Master node of the migration.
+	pub master: Option<String>,
+	/// Key servers that are participating in the migration.
+	pub participants: Vec<String>,
+	/// True if migration has been confirmed by this key server.
+	pub is_confirmed: bool,
+	/// Key servers that have confirmed migration.
+	pub confirmed_by: Vec<String>,
+	/// Our last start migration transaction.
+	pub start_migration_tx: Option<MigrationTransactionStatus>,
+	/// Our last confirm migration transaction.
+	pub confirm_migration_tx: Option<MigrationTransactionStatus>,
+	/// Migration that operator has decided to abstain from.
+	pub abstained_migration: Option<String>,
+}
+
+/// State of our last migration transaction.
+#[derive(Debug, Serialize)]
+pub struct MigrationTransactionStatus {
+	/// Migration ID.
+	pub migration_id: String,
+	/// Number of best block when transaction has been sent.
+	pub block: u32,
+	/// True if transaction has been successfully dispatched in finalized block.
+	pub is_finalized: bool,
+	/// Number of failed attempts to submit this transaction.
+	pub failures: u32,
+}
+
+/// Type of migration transaction.
+#[derive(Clone, Copy, Debug)]
+enum MigrationTransactionType {
+	/// Start migration transaction.
+	Start,
+	/// Confirm migration transaction.
+	Confirm,
 }
 
 impl OnChainKeyServerSet {
-	pub fn new(client: Client, self_id: KeyServerId) -> Self {
+	pub fn new(
+		client: Client,
+		bridge: AsyncBridge,
+		self_id: KeyServerId,
+		migration_state_file: Option<PathBuf>,
+	) -> Self {
+		let (start_migration_tx, confirm_migration_tx) = match migration_state_file {
+			Some(ref migration_state_file) => read_migration_transactions(migration_state_file),
+			None => {
+				warn!(
+					target: "secretstore_net",
+					"Migration state file is not specified. Migration transactions may be resubmitted after restart",
+				);
+
+				(None, None)
+			},
+		};
+
 		OnChainKeyServerSet {
 			client,
+			bridge,
 			self_id,
-			data: RwLock::new(OnChainKeyServerSetData {
+			address_resolver: AddressResolver::default(),
+			data: Arc::new(RwLock::new(OnChainKeyServerSetData {
 				best_block: None,
 				best_block_snapshot: KeyServerSetSnapshot {
 					current_set: BTreeMap::new(),
 					new_set: BTreeMap::new(),
 					migration: None,
 				},
-				start_migration_tx: None,
-				confirm_migration_tx: None,
-			}),
+				migration_confirmations: BTreeSet::new(),
+				start_migration_tx,
+				confirm_migration_tx,
+				abstained_migration: None,
+				migration_state_file,
+			})),
+			persist_lock: Arc::new(Mutex::new(())),
 		}
 	}
 
-	pub fn set_best_block(&self, best_block: (u32, H256)) {
-		self.data.write().best_block = Some(best_block);
+	/// Set best block and read key server set snapshot at this block.
+	pub async fn set_best_block(&self, best_block: (u32, H256)) {
+		let snapshot = match self.read_snapshot(best_block.1).await {
+			Ok(snapshot) => match snapshot.migration.as_ref() {
+				Some(migration) => self.read_migration_confirmations(best_block.1, migration).await
+					.map(|confirmations| (snapshot, confirmations)),
+				None => Ok((snapshot, BTreeSet::new())),
+			},
+			Err(error) => Err(error),
+		};
+
+		let mut data = self.data.write();
+		data.best_block = Some(best_block);
+		match snapshot {
+			Ok((snapshot, migration_confirmations)) => {
+				data.best_block_snapshot = snapshot;
+				data.migration_confirmations = migration_confirmations;
+			},
+			Err(error) => error!(
+				target: "secretstore_net",
+				"Failed to read key server set at block {}: {:?}",
+				best_block.1,
+				error,
+			),
+		}
+	}
+
+	/// Forget about migration transactions that have been sent at retracted blocks.
+	pub fn retract_blocks(&self, retracted: &[(u32, H256)]) {
+		let mut is_changed = false;
+		let mut data = self.data.write();
+		for transaction_type in &[MigrationTransactionType::Start, MigrationTransactionType::Confirm] {
+			let previous_transaction = data.migration_tx(*transaction_type);
+			let is_retracted = match previous_transaction.as_ref() {
+				Some(tx) => !tx.is_finalized && retracted.contains(&tx.block),
+				None => false,
+			};
+			if is_retracted {
+				info!(
+					target: "secretstore_net",
+					"{:?} migration transaction will be resubmitted on next attempt: it has been sent at retracted block",
+					transaction_type,
+				);
+
+				*previous_transaction = None;
+				is_changed = true;
+			}
+		}
+
+		drop(data);
+
+		if is_changed {
+			persist_migration_transactions(&self.data, &self.persist_lock);
+		}
+	}
+
+	/// Get current migration status.
+	pub fn migration_status(&self) -> MigrationStatus {
+		let data = self.data.read();
+		let migration = data.best_block_snapshot.migration.as_ref();
+		MigrationStatus {
+			best_block: data.best_block.map(|best_block| best_block.0),
+			migration_id: migration.map(|migration| format!("{:?}", migration.id)),
+			master: migration.map(|migration| format!("{:?}", migration.master)),
+			participants: migration
+				.map(|migration| migration.set.keys().map(|id| format!("{:?}", id)).collect())
+				.unwrap_or_default(),
+			is_confirmed: migration.map(|migration| migration.is_confirmed).unwrap_or(false),
+			confirmed_by: data.migration_confirmations.iter().map(|id| format!("{:?}", id)).collect(),
+			start_migration_tx: data.start_migration_tx.as_ref().map(transaction_status),
+			confirm_migration_tx: data.confirm_migration_tx.as_ref().map(transaction_status),
+			abstained_migration: data.abstained_migration.as_ref().map(|id| format!("{:?}", id)),
+		}
+	}
+
+	/// Forget about previous migration transactions, so that they are resubmitted on next attempt.
+	pub fn force_retry(&self) {
+		{
+			let mut data = self.data.write();
+			data.start_migration_tx = None;
+			data.confirm_migration_tx = None;
+		}
+		persist_migration_transactions(&self.data, &self.persist_lock);
+
+		info!(
+			target: "secretstore_net",
+			"Migration transactions will be resubmitted on next attempt: requested by operator",
+		);
+	}
+
+	/// Stop submitting transactions for given migration.
+	pub fn abstain(&self, migration_id: MigrationId) {
+		info!(
+			target: "secretstore_net",
+			"Abstaining from migration {:?}: requested by operator",
+			migration_id,
+		);
+
+		self.data.write().abstained_migration = Some(migration_id);
+	}
+
+	/// Resume submitting transactions for migration that we have abstained from.
+	pub fn resume(&self) {
+		if let Some(migration_id) = self.data.write().abstained_migration.take() {
+			info!(
+				target: "secretstore_net",
+				"Resuming participation in migration {:?}: requested by operator",
+				migration_id,
+			);
+		}
+	}
+
+	/// Read key server set snapshot at given block.
+	async fn read_snapshot(
+		&self,
+		block_hash: H256,
+	) -> Result<KeyServerSetSnapshot<SocketAddr>, crate::substrate_client::Error> {
+		let snapshot: ss_primitives::key_server_set::KeyServerSetSnapshot = self.client.call_runtime_method(
+			block_hash,
+			"SecretStoreKeyServerSetApi_snapshot",
+			vec![self.self_id.encode()],
+		).await?;
+
+		// forget resolved addresses of key servers that have left the set
+		let migration_set = snapshot.migration.iter().flat_map(|migration| migration.set.iter());
+		self.address_resolver.retain(
+			snapshot.current_set.iter()
+				.chain(snapshot.new_set.iter())
+				.chain(migration_set)
+				.map(|key_server| key_server.address.as_slice())
+		);
+
+		let migration = match snapshot.migration {
+			Some(migration) => Some(KeyServerSetMigration {
+				id: migration.id,
+				set: self.resolve_key_servers(migration.set).await,
+				master: migration.master,
+				is_confirmed: migration.is_confirmed,
+			}),
+			None => None,
+		};
+		Ok(KeyServerSetSnapshot {
+			current_set: self.resolve_key_servers(snapshot.current_set).await,
+			new_set: self.resolve_key_servers(snapshot.new_set).await,
+			migration,
+		})
+	}
+
+	/// Read key servers that have confirmed migration at given block.
+	async fn read_migration_confirmations(
+		&self,
+		block_hash: H256,
+		migration: &KeyServerSetMigration<SocketAddr>,
+	) -> Result<BTreeSet<KeyServerId>, crate::substrate_client::Error> {
+		let participants = migration.set.keys().cloned().collect::<Vec<_>>();
+		let snapshots = futures::future::try_join_all(participants.iter().map(|participant| {
+			self.client.call_runtime_method::<ss_primitives::key_server_set::KeyServerSetSnapshot>(
+				block_hash,
+				"SecretStoreKeyServerSetApi_snapshot",
+				vec![participant.encode()],
+			)
+		})).await?;
+
+		Ok(participants
+			.into_iter()
+			.zip(snapshots)
+			.filter(|(_, snapshot)| snapshot.migration.as_ref()
+				.map(|participant_migration| participant_migration.id == migration.id && participant_migration.is_confirmed)
+				.unwrap_or(false))
+			.map(|(participant, _)| participant)
+			.collect())
+	}
+
+	/// Resolve network addresses of key servers. If address of key server can not be resolved,
+	/// last known address of this key server is used. If there's no such address, key server
+	/// is left out of the set, until its address is resolved.
+	async fn resolve_key_servers(
+		&self,
+		key_servers: Vec<ss_primitives::key_server_set::KeyServerNetworkAddress>,
+	) -> BTreeMap<KeyServerId, SocketAddr> {
+		let resolved_addresses = futures::future::join_all(
+			key_servers.iter().map(|key_server| self.address_resolver.resolve(&key_server.address))
+		).await;
+
+		key_servers
+			.into_iter()
+			.zip(resolved_addresses)
+			.filter_map(|(key_server, address)| match address {
+				Ok(address) => Some((key_server.id, address)),
+				Err(error) => match self.last_known_address(&key_server.id) {
+					Some(address) => {
+						warn!(
+							target: "secretstore_net",
+							"Failed to resolve address of key server {}: {}. Using last known address {}",
+							key_server.id,
+							error,
+							address,
+						);
+
+						Some((key_server.id, address))
+					},
+					None => {
+						warn!(
+							target: "secretstore_net",
+							"Failed to resolve address of key server {}: {}. Key server is excluded from the set",
+							key_server.id,
+							error,
+						);
+
+						None
+					},
+				},
+			})
+			.collect()
+	}
+
+	/// Get last known address of the key server.
+	fn last_known_address(&self, key_server_id: &KeyServerId) -> Option<SocketAddr> {
+		let data = self.data.read();
+		let snapshot = &data.best_block_snapshot;
+		snapshot.current_set.get(key_server_id)
+			.or_else(|| snapshot.new_set.get(key_server_id))
+			.or_else(|| snapshot.migration.as_ref().and_then(|migration| migration.set.get(key_server_id)))
+			.cloned()
 	}
 }
 
@@ -72,91 +421,314 @@ impl KeyServerSet for OnChainKeyServerSet {
 	}
 
 	fn start_migration(&self, migration_id: MigrationId) {
-		{
+		self.submit_migration_transaction(MigrationTransactionType::Start, migration_id)
+	}
+
+	fn confirm_migration(&self, migration_id: MigrationId) {
+		self.submit_migration_transaction(MigrationTransactionType::Confirm, migration_id)
+	}
+}
+
+impl OnChainKeyServerSet {
+	/// Submit migration transaction (if required) and watch its outcome in the background.
+	fn submit_migration_transaction(&self, transaction_type: MigrationTransactionType, migration_id: MigrationId) {
+		let decision = {
 			let mut data = self.data.write();
-			let best_block = match data.best_block {
-				Some(best_block) => best_block,
-				None => return,
+			let decision = match data.best_block {
+				None => SubmissionDecision::Skip("best block is unknown"),
+				Some(_) if data.abstained_migration.as_ref() == Some(&migration_id) =>
+					SubmissionDecision::Skip("operator has decided to abstain from this migration"),
+				Some(best_block) =>
+					update_last_transaction_block(best_block, &migration_id, data.migration_tx(transaction_type)),
 			};
-			if !update_last_transaction_block(best_block, &migration_id, &mut data.start_migration_tx) {
+			decision
+		};
+
+		match decision {
+			SubmissionDecision::Submit(reason) => {
+				persist_migration_transactions(&self.data, &self.persist_lock);
+
+				info!(
+					target: "secretstore_net",
+					"Submitting {:?} migration transaction for migration {:?}: {}",
+					transaction_type,
+					migration_id,
+					reason,
+				);
+			},
+			SubmissionDecision::Skip(reason) => {
+				info!(
+					target: "secretstore_net",
+					"Not submitting {:?} migration transaction for migration {:?}: {}",
+					transaction_type,
+					migration_id,
+					reason,
+				);
+
 				return;
-			}
+			},
 		}
 
-		let submit_result = futures::executor::block_on(async {
-			self.client.submit_transaction(node_runtime::Call::SecretStore(
-				node_runtime::SecretStoreCall::start_migration(
-					migration_id,
-				),
-			)).await
+		let call = match transaction_type {
+			MigrationTransactionType::Start =>
+				crate::runtime::SecretStoreCall::start_migration(migration_id.clone()),
+			MigrationTransactionType::Confirm =>
+				crate::runtime::SecretStoreCall::confirm_migration(migration_id.clone()),
+		};
+
+		let client = self.client.clone();
+		let data = self.data.clone();
+		let persist_lock = self.persist_lock.clone();
+		self.bridge.spawn(async move {
+			let outcome = client.submit_and_watch_transaction(DefaultRuntime::secret_store_call(call)).await;
+			on_migration_transaction_outcome(&data, &persist_lock, transaction_type, &migration_id, outcome);
 		});
+	}
+}
 
-		if let Err(error) = submit_result {
+impl OnChainKeyServerSetData {
+	/// Get migration state file path and previous migration transactions that should be written there.
+	fn persistent_migration_transactions(&self) -> Option<(PathBuf, PersistentMigrationTransactions)> {
+		let path = self.migration_state_file.clone()?;
+		let to_persistent = |transaction: &Option<PreviousMigrationTransaction>| transaction
+			.as_ref()
+			.map(|transaction| PersistentMigrationTransaction {
+				migration_id: format!("{:x}", transaction.migration_id),
+				block_number: transaction.block.0,
+				block_hash: format!("{:x}", transaction.block.1),
+				is_finalized: transaction.is_finalized,
+				failures: transaction.failures,
+			});
+		Some((path, PersistentMigrationTransactions {
+			start_migration_tx: to_persistent(&self.start_migration_tx),
+			confirm_migration_tx: to_persistent(&self.confirm_migration_tx),
+		}))
+	}
+
+	/// Get reference to previous migration transaction of given type.
+	fn migration_tx(&mut self, transaction_type: MigrationTransactionType) -> &mut Option<PreviousMigrationTransaction> {
+		match transaction_type {
+			MigrationTransactionType::Start => &mut self.start_migration_tx,
+			MigrationTransactionType::Confirm => &mut self.confirm_migration_tx,
+		}
+	}
+}
+
+/// Get status of previous migration transaction.
+fn transaction_status(transaction: &PreviousMigrationTransaction) -> MigrationTransactionStatus {
+	MigrationTransactionStatus {
+		migration_id: format!("{:?}", transaction.migration_id),
+		block: transaction.block.0,
+		is_finalized: transaction.is_finalized,
+		failures: transaction.failures,
+	}
+}
+
+/// Update previous migration transaction using its outcome.
+fn on_migration_transaction_outcome(
+	data: &RwLock<OnChainKeyServerSetData>,
+	persist_lock: &Mutex<()>,
+	transaction_type: MigrationTransactionType,
+	migration_id: &MigrationId,
+	outcome: Result<TransactionOutcome, crate::substrate_client::Error>,
+) {
+	let is_finalized = match outcome {
+		Ok(TransactionOutcome::Finalized { transaction_hash, block_hash, dispatch_result: Ok(()) }) => {
+			info!(
+				target: "secretstore_net",
+				"{:?} migration transaction {} has been finalized in block {}",
+				transaction_type,
+				transaction_hash,
+				block_hash,
+			);
+
+			true
+		},
+		Ok(TransactionOutcome::Finalized { transaction_hash, block_hash, dispatch_result: Err(error) }) => {
+			warn!(
+				target: "secretstore_net",
+				"{:?} migration transaction {} has failed in block {}: {:?}",
+				transaction_type,
+				transaction_hash,
+				block_hash,
+				error,
+			);
+
+			false
+		},
+		Ok(TransactionOutcome::Unknown) => {
+			// fall back to retrying after TRANSACTION_RETRY_INTERVAL_BLOCKS
+			return;
+		},
+		Ok(outcome) => {
+			warn!(
+				target: "secretstore_net",
+				"{:?} migration transaction has not been included into finalized block: {:?}",
+				transaction_type,
+				outcome,
+			);
+
+			false
+		},
+		Err(error) => {
 			error!(
 				target: "secretstore_net",
-				"Error submitting start migration transaction: {:?}",
+				"Error submitting {:?} migration transaction: {:?}",
+				transaction_type,
 				error,
 			);
+
+			false
+		},
+	};
+
+	{
+		let mut data = data.write();
+		let previous_transaction = data.migration_tx(transaction_type);
+		match previous_transaction.as_mut() {
+			Some(tx) if tx.migration_id == *migration_id => {
+				if is_finalized {
+					tx.is_finalized = true;
+				} else {
+					// transaction has failed => retry with backoff
+					tx.failures += 1;
+					info!(
+						target: "secretstore_net",
+						"{:?} migration transaction will be resubmitted in {} blocks after previous attempt",
+						transaction_type,
+						retry_interval_blocks(tx.failures),
+					);
+				}
+			},
+			_ => return,
 		}
 	}
 
-	fn confirm_migration(&self, migration_id: MigrationId) {
-		{
-			let mut data = self.data.write();
-			let best_block = match data.best_block {
-				Some(best_block) => best_block,
-				None => return,
-			};
-			if !update_last_transaction_block(best_block, &migration_id, &mut data.confirm_migration_tx) {
-				return;
-			}
-		}
+	persist_migration_transactions(data, persist_lock);
+}
 
-		let submit_result = futures::executor::block_on(async {
-			self.client.submit_transaction(node_runtime::Call::SecretStore(
-				node_runtime::SecretStoreCall::confirm_migration(
-					migration_id,
-				),
-			)).await
-		});
+/// Write previous migration transactions to the migration state file. Transactions are copied
+/// under the data lock and written outside of it. Writes are serialized, so that older state
+/// never overwrites the newer one.
+fn persist_migration_transactions(data: &RwLock<OnChainKeyServerSetData>, persist_lock: &Mutex<()>) {
+	let _persist_guard = persist_lock.lock();
+	let (path, transactions) = match data.read().persistent_migration_transactions() {
+		Some(persistent_transactions) => persistent_transactions,
+		None => return,
+	};
+
+	let write_result = serde_json::to_vec(&transactions)
+		.map_err(|error| error.to_string())
+		.and_then(|contents| crate::atomic_file::write(&path, &contents).map_err(|error| error.to_string()));
+	if let Err(error) = write_result {
+		error!(
+			target: "secretstore_net",
+			"Failed to write migration state file {}: {}",
+			path.display(),
+			error,
+		);
+	}
+}
+
+/// Read previous migration transactions from the migration state file.
+fn read_migration_transactions(
+	path: &Path,
+) -> (Option<PreviousMigrationTransaction>, Option<PreviousMigrationTransaction>) {
+	let transactions = match std::fs::read_to_string(path) {
+		Ok(contents) => serde_json::from_str::<PersistentMigrationTransactions>(&contents)
+			.map_err(|error| error.to_string()),
+		Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+		Err(error) => Err(error.to_string()),
+	};
 
-		if let Err(error) = submit_result {
+	let transactions = match transactions {
+		Ok(transactions) => transactions,
+		Err(error) => {
 			error!(
 				target: "secretstore_net",
-				"Error submitting confirm migration transaction: {:?}",
+				"Failed to read migration state file {}: {}. Migration transactions may be resubmitted",
+				path.display(),
 				error,
 			);
-		}
-	}
+
+			return (None, None);
+		},
+	};
+
+	let from_persistent = |transaction: Option<PersistentMigrationTransaction>| transaction
+		.and_then(|transaction| {
+			let migration_id = transaction.migration_id.parse().ok()?;
+			let block_hash = transaction.block_hash.parse().ok()?;
+			info!(
+				target: "secretstore_net",
+				"Restored migration transaction for migration {:?}, sent at block {}",
+				migration_id,
+				transaction.block_number,
+			);
+
+			Some(PreviousMigrationTransaction {
+				migration_id,
+				block: (transaction.block_number, block_hash),
+				is_finalized: transaction.is_finalized,
+				failures: transaction.failures,
+			})
+		});
+
+	(
+		from_persistent(transactions.start_migration_tx),
+		from_persistent(transactions.confirm_migration_tx),
+	)
 }
 
+/// Decide whether migration transaction needs to be submitted and remember it, if so.
 fn update_last_transaction_block(
 	best_block: (u32, H256),
 	migration_id: &MigrationId,
 	previous_transaction: &mut Option<PreviousMigrationTransaction>,
-) -> bool {
-	match previous_transaction.as_ref() {
-		// no previous transaction => send immediately
-		None => (),
-		// previous transaction has been sent for other migration process => send immediately
-		Some(tx) if tx.migration_id != *migration_id => (),
+) -> SubmissionDecision {
+	let decision = match previous_transaction.as_ref() {
+		None => SubmissionDecision::Submit("transaction has not been sent yet"),
+		Some(tx) if tx.migration_id != *migration_id =>
+			SubmissionDecision::Submit("previous transaction has been sent for other migration"),
+		Some(tx) if tx.is_finalized => SubmissionDecision::Skip("transaction has been finalized"),
+		// if previous transaction has failed recently => wait before retrying, so that
+		// deterministically failing transaction doesn't pay fees at every block
+		Some(tx) if tx.failures != 0 && is_sent_recently(tx, best_block) =>
+			SubmissionDecision::Skip("previous transaction has failed recently"),
+		Some(tx) if tx.failures != 0 => SubmissionDecision::Submit("previous transaction has failed"),
 		// if we have sent the same type of transaction recently => do nothing (hope it will be mined eventually)
+		Some(tx) if is_sent_recently(tx, best_block) =>
+			SubmissionDecision::Skip("transaction has been sent recently"),
 		// if we have sent the same transaction some time ago =>
 		//   assume that our tx queue was full
-		//   or we didn't have enough eth fot this tx
-		//   or the transaction has been removed from the queue (and never reached any miner node)
-		// if we have restarted after sending tx => assume we have never sent it
-		Some(tx) => {
-			if tx.block.0 > best_block.0 || best_block.0 - tx.block.0 < TRANSACTION_RETRY_INTERVAL_BLOCKS {
-				return false;
-			}
-		},
+		//   or we didn't have enough funds for this tx
+		//   or the transaction has been removed from the queue (and never reached any block author)
+		Some(_) => SubmissionDecision::Submit("transaction has not been finalized in time"),
+	};
+
+	if let SubmissionDecision::Submit(_) = decision {
+		let failures = match previous_transaction.as_ref() {
+			Some(tx) if tx.migration_id == *migration_id => tx.failures,
+			_ => 0,
+		};
+		*previous_transaction = Some(PreviousMigrationTransaction {
+			migration_id: migration_id.clone(),
+			block: best_block,
+			is_finalized: false,
+			failures,
+		});
 	}
 
-	*previous_transaction = Some(PreviousMigrationTransaction {
-		migration_id: migration_id.clone(),
-		block: best_block,
-	});
+	decision
+}
+
+/// Returns true if transaction has been sent less than retry interval blocks ago.
+fn is_sent_recently(transaction: &PreviousMigrationTransaction, best_block: (u32, H256)) -> bool {
+	transaction.block.0 > best_block.0
+		|| best_block.0 - transaction.block.0 < retry_interval_blocks(transaction.failures)
+}
 
-	true
+/// Number of blocks between transaction retries after given number of failures.
+fn retry_interval_blocks(failures: u32) -> u32 {
+	TRANSACTION_RETRY_INTERVAL_BLOCKS << std::cmp::min(failures, MAX_RETRY_BACKOFF_EXPONENT)
 }
@@ -0,0 +1,463 @@
+use std::{
+	collections::BTreeMap,
+	path::Path,
+	sync::Arc,
+};
+use kvdb::{DBTransaction, KeyValueDB};
+use kvdb_rocksdb::{Database as RocksDatabase, DatabaseConfig};
+use log::{error, info};
+use parity_crypto::publickey::Secret;
+use parity_secretstore_primitives::{
+	ServerKeyId,
+	error::Error,
+	key_storage::{DocumentKeyShare, DocumentKeyShareVersion, KeyStorage},
+};
+use serde::{Deserialize, Serialize};
+
+/// Column where key shares are stored.
+const SHARES_COLUMN: u32 = 0;
+/// Column where database metadata is stored.
+const META_COLUMN: u32 = 1;
+/// Total number of columns.
+const COLUMNS: u32 = 2;
+/// Key of database version in META_COLUMN.
+const VERSION_KEY: &'static [u8] = b"version";
+/// Current database version.
+const CURRENT_VERSION: u8 = 1;
+
+/// Single database write operation.
+pub enum DatabaseOperation {
+	/// Insert or replace value.
+	Insert(Vec<u8>, Vec<u8>),
+	/// Delete value.
+	Delete(Vec<u8>),
+	/// Insert or replace database metadata value.
+	InsertMeta(Vec<u8>, Vec<u8>),
+}
+
+/// Key-value database that stores serialized key shares.
+pub trait Database: Send + Sync {
+	/// Read value from the database.
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+	/// Read database metadata value.
+	fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+	/// Atomically apply all operations to the database.
+	fn write(&self, operations: Vec<DatabaseOperation>) -> Result<(), Error>;
+	/// Iterate over all database entries.
+	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
+}
+
+/// RocksDB-backed database. Every write is applied as a single batch that
+/// goes through RocksDB write-ahead log, so partially applied writes are never
+/// observed after crash.
+pub struct RocksDbDatabase {
+	/// Database itself.
+	db: Arc<dyn KeyValueDB>,
+}
+
+/// Key storage that keeps serialized key shares in the database.
+pub struct PersistentKeyStorage<D> {
+	/// Underlying database.
+	db: D,
+}
+
+/// Serializable version of DocumentKeyShare.
+#[derive(Serialize, Deserialize)]
+struct SerializableDocumentKeyShare {
+	author: String,
+	threshold: usize,
+	public: String,
+	common_point: Option<String>,
+	encrypted_point: Option<String>,
+	versions: Vec<SerializableDocumentKeyShareVersion>,
+}
+
+/// Serializable version of DocumentKeyShareVersion.
+#[derive(Serialize, Deserialize)]
+struct SerializableDocumentKeyShareVersion {
+	hash: String,
+	id_numbers: BTreeMap<String, String>,
+	secret_share: String,
+}
+
+impl RocksDbDatabase {
+	/// Open (or create) database at given path.
+	pub fn open(path: &Path) -> Result<Self, Error> {
+		let db_path = path.to_str()
+			.ok_or_else(|| Error::Database(format!("Invalid database path: {}", path.display())))?;
+		let db = RocksDatabase::open(&DatabaseConfig::with_columns(COLUMNS), db_path)
+			.map_err(|error| Error::Database(format!("{}", error)))?;
+		Ok(RocksDbDatabase {
+			db: Arc::new(db),
+		})
+	}
+}
+
+impl Database for RocksDbDatabase {
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.db.get(SHARES_COLUMN, key)
+			.map(|value| value.map(|value| value.to_vec()))
+			.map_err(|error| Error::Database(format!("{}", error)))
+	}
+
+	fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.db.get(META_COLUMN, key)
+			.map(|value| value.map(|value| value.to_vec()))
+			.map_err(|error| Error::Database(format!("{}", error)))
+	}
+
+	fn write(&self, operations: Vec<DatabaseOperation>) -> Result<(), Error> {
+		let mut transaction = DBTransaction::new();
+		for operation in operations {
+			match operation {
+				DatabaseOperation::Insert(key, value) => transaction.put(SHARES_COLUMN, &key, &value),
+				DatabaseOperation::Delete(key) => transaction.delete(SHARES_COLUMN, &key),
+				DatabaseOperation::InsertMeta(key, value) => transaction.put(META_COLUMN, &key, &value),
+			}
+		}
+		self.db.write(transaction).map_err(|error| Error::Database(format!("{}", error)))
+	}
+
+	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+		Box::new(self.db.iter(SHARES_COLUMN).map(|(key, value)| (key.into_vec(), value.into_vec())))
+	}
+}
+
+impl<D: Database> PersistentKeyStorage<D> {
+	/// Create key storage backed by given database. When database is opened for the first
+	/// time, key shares from the import file (if specified) are written to the database,
+	/// together with the database version, in a single batch.
+	pub fn open(db: D, import_file: Option<&Path>) -> Result<Self, Error> {
+		match db.get_meta(VERSION_KEY)?.as_ref().map(|version| &version[..]) {
+			None => initialize(&db, import_file)?,
+			Some(&[CURRENT_VERSION]) => (),
+			Some(version) => return Err(Error::Database(format!("Unsupported key storage database version: {:?}", version))),
+		}
+
+		Ok(PersistentKeyStorage {
+			db,
+		})
+	}
+}
+
+impl<D: Database> KeyStorage for PersistentKeyStorage<D> {
+	fn insert(&self, document: ServerKeyId, key: DocumentKeyShare) -> Result<(), Error> {
+		let key = serialize_key_share(key)?;
+		self.db.write(vec![DatabaseOperation::Insert(document.as_bytes().to_vec(), key)])
+	}
+
+	fn update(&self, document: ServerKeyId, key: DocumentKeyShare) -> Result<(), Error> {
+		self.insert(document, key)
+	}
+
+	fn get(&self, document: &ServerKeyId) -> Result<Option<DocumentKeyShare>, Error> {
+		self.db.get(document.as_bytes())?
+			.map(|key| deserialize_key_share(&key))
+			.transpose()
+	}
+
+	fn remove(&self, document: &ServerKeyId) -> Result<(), Error> {
+		self.db.write(vec![DatabaseOperation::Delete(document.as_bytes().to_vec())])
+	}
+
+	fn clear(&self) -> Result<(), Error> {
+		let operations = self.db.iter()
+			.map(|(key, _)| DatabaseOperation::Delete(key))
+			.collect();
+		self.db.write(operations)
+	}
+
+	fn contains(&self, document: &ServerKeyId) -> bool {
+		self.db.get(document.as_bytes())
+			.map(|key| key.is_some())
+			.unwrap_or(false)
+	}
+
+	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (ServerKeyId, DocumentKeyShare)> + 'a> {
+		Box::new(self.db.iter().filter_map(|(document, key)| {
+			let key = match deserialize_key_share(&key) {
+				Ok(key) => key,
+				Err(error) => {
+					error!(
+						target: "secretstore",
+						"Failed to read key share {}: {:?}",
+						hex::encode(&document),
+						error,
+					);
+
+					return None;
+				},
+			};
+
+			Some((ServerKeyId::from_slice(&document), key))
+		}))
+	}
+}
+
+impl<D: Database + ?Sized> Database for Arc<D> {
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		(**self).get(key)
+	}
+
+	fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		(**self).get_meta(key)
+	}
+
+	fn write(&self, operations: Vec<DatabaseOperation>) -> Result<(), Error> {
+		(**self).write(operations)
+	}
+
+	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+		(**self).iter()
+	}
+}
+
+/// Initialize fresh database, importing key shares from given file. Import file is
+/// a JSON object that maps hex-encoded server key ids to key shares, serialized the
+/// same way they're stored in the database.
+fn initialize(db: &dyn Database, import_file: Option<&Path>) -> Result<(), Error> {
+	let mut operations = Vec::new();
+	if let Some(import_file) = import_file {
+		let contents = std::fs::read(import_file)
+			.map_err(|error| Error::Database(format!("Failed to read {}: {}", import_file.display(), error)))?;
+		let shares: BTreeMap<String, serde_json::Value> = serde_json::from_slice(&contents)
+			.map_err(|error| Error::Database(format!("Invalid key shares file {}: {}", import_file.display(), error)))?;
+		for (document, key) in shares {
+			let document: ServerKeyId = decode_hash(document.trim_start_matches("0x"))?;
+			let key = serde_json::to_vec(&key).map_err(|error| Error::Database(format!("{}", error)))?;
+			// check that share is valid before importing it
+			let key = serialize_key_share(deserialize_key_share(&key)?)?;
+			operations.push(DatabaseOperation::Insert(document.as_bytes().to_vec(), key));
+		}
+	}
+
+	info!(
+		target: "secretstore",
+		"Initializing key storage database (version {}). Imported {} key shares",
+		CURRENT_VERSION,
+		operations.len(),
+	);
+
+	operations.push(DatabaseOperation::InsertMeta(VERSION_KEY.to_vec(), vec![CURRENT_VERSION]));
+	db.write(operations)
+}
+
+/// Serialize key share.
+fn serialize_key_share(key: DocumentKeyShare) -> Result<Vec<u8>, Error> {
+	let key = SerializableDocumentKeyShare {
+		author: hex::encode(key.author.as_bytes()),
+		threshold: key.threshold,
+		public: hex::encode(key.public.as_bytes()),
+		common_point: key.common_point.map(|point| hex::encode(point.as_bytes())),
+		encrypted_point: key.encrypted_point.map(|point| hex::encode(point.as_bytes())),
+		versions: key.versions.into_iter().map(|version| SerializableDocumentKeyShareVersion {
+			hash: hex::encode(version.hash.as_bytes()),
+			id_numbers: version.id_numbers.into_iter()
+				.map(|(node_id, id_number)| (hex::encode(node_id.as_bytes()), hex::encode(id_number.as_bytes())))
+				.collect(),
+			secret_share: hex::encode(version.secret_share.as_bytes()),
+		}).collect(),
+	};
+
+	serde_json::to_vec(&key).map_err(|error| Error::Database(format!("{}", error)))
+}
+
+/// Deserialize key share.
+fn deserialize_key_share(key: &[u8]) -> Result<DocumentKeyShare, Error> {
+	let key: SerializableDocumentKeyShare = serde_json::from_slice(key)
+		.map_err(|error| Error::Database(format!("{}", error)))?;
+	Ok(DocumentKeyShare {
+		author: decode_hash(&key.author)?,
+		threshold: key.threshold,
+		public: decode_hash(&key.public)?,
+		common_point: key.common_point.as_ref().map(|point| decode_hash(point)).transpose()?,
+		encrypted_point: key.encrypted_point.as_ref().map(|point| decode_hash(point)).transpose()?,
+		versions: key.versions.into_iter().map(|version| Ok(DocumentKeyShareVersion {
+			hash: decode_hash(&version.hash)?,
+			id_numbers: version.id_numbers.iter()
+				.map(|(node_id, id_number)| Ok((decode_hash(node_id)?, decode_secret(id_number)?)))
+				.collect::<Result<_, Error>>()?,
+			secret_share: decode_secret(&version.secret_share)?,
+		})).collect::<Result<_, Error>>()?,
+	})
+}
+
+/// Decode hex-encoded fixed-size hash.
+fn decode_hash<T: Default + AsMut<[u8]>>(value: &str) -> Result<T, Error> {
+	let mut hash = T::default();
+	hex::decode_to_slice(value, hash.as_mut())
+		.map_err(|error| Error::Database(format!("{}", error)))?;
+	Ok(hash)
+}
+
+/// Decode hex-encoded secret.
+fn decode_secret(value: &str) -> Result<Secret, Error> {
+	let secret = hex::decode(value).map_err(|error| Error::Database(format!("{}", error)))?;
+	Secret::copy_from_slice(&secret).ok_or_else(|| Error::Database("Invalid secret".into()))
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::collections::BTreeMap;
+	use parking_lot::Mutex;
+	use parity_crypto::publickey::{Address, Public, Secret};
+	use parity_secretstore_primitives::{
+		ServerKeyId,
+		error::Error,
+		key_storage::{DocumentKeyShare, DocumentKeyShareVersion, KeyStorage},
+	};
+	use super::*;
+
+	/// In-memory database.
+	#[derive(Default)]
+	pub struct MemoryDatabase {
+		/// Key shares column.
+		pub values: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+		/// Metadata column.
+		pub meta: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+	}
+
+	impl Database for MemoryDatabase {
+		fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+			Ok(self.values.lock().get(key).cloned())
+		}
+
+		fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+			Ok(self.meta.lock().get(key).cloned())
+		}
+
+		fn write(&self, operations: Vec<DatabaseOperation>) -> Result<(), Error> {
+			let mut values = self.values.lock();
+			let mut meta = self.meta.lock();
+			for operation in operations {
+				match operation {
+					DatabaseOperation::Insert(key, value) => { values.insert(key, value); },
+					DatabaseOperation::Delete(key) => { values.remove(&key); },
+					DatabaseOperation::InsertMeta(key, value) => { meta.insert(key, value); },
+				}
+			}
+			Ok(())
+		}
+
+		fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+			Box::new(self.values.lock().clone().into_iter())
+		}
+	}
+
+	pub fn key_share(seed: u8) -> DocumentKeyShare {
+		let secret = |value: u8| Secret::copy_from_slice(&[value; 32]).unwrap();
+		DocumentKeyShare {
+			author: Address::from_low_u64_be(seed as u64),
+			threshold: seed as usize,
+			public: Public::from_low_u64_be(seed as u64 + 1),
+			common_point: Some(Public::from_low_u64_be(seed as u64 + 2)),
+			encrypted_point: None,
+			versions: vec![DocumentKeyShareVersion {
+				hash: [seed; 32].into(),
+				id_numbers: vec![
+					(Public::from_low_u64_be(1), secret(seed + 1)),
+					(Public::from_low_u64_be(2), secret(seed + 2)),
+				].into_iter().collect(),
+				secret_share: secret(seed + 3),
+			}],
+		}
+	}
+
+	fn temp_path(name: &str) -> std::path::PathBuf {
+		let mut path = std::env::temp_dir();
+		path.push(format!("ss-key-storage-{}-{}-{}", name, std::process::id(), rand::random::<u64>()));
+		path
+	}
+
+	#[test]
+	fn key_share_serialization_roundtrips() {
+		let share = key_share(10);
+		let serialized = serialize_key_share(share.clone()).unwrap();
+		assert_eq!(deserialize_key_share(&serialized).unwrap(), share);
+	}
+
+	#[test]
+	fn invalid_key_share_is_rejected() {
+		assert!(deserialize_key_share(b"{}").is_err());
+		let mut serialized: serde_json::Value = serde_json::from_slice(
+			&serialize_key_share(key_share(10)).unwrap(),
+		).unwrap();
+		serialized["public"] = "00".into();
+		assert!(deserialize_key_share(&serde_json::to_vec(&serialized).unwrap()).is_err());
+	}
+
+	#[test]
+	fn key_storage_works() {
+		let storage = PersistentKeyStorage::open(MemoryDatabase::default(), None).unwrap();
+		let (id1, id2) = (ServerKeyId::from_low_u64_be(1), ServerKeyId::from_low_u64_be(2));
+		storage.insert(id1, key_share(1)).unwrap();
+		storage.insert(id2, key_share(2)).unwrap();
+		assert!(storage.contains(&id1));
+		assert_eq!(storage.get(&id1).unwrap(), Some(key_share(1)));
+
+		storage.update(id1, key_share(3)).unwrap();
+		assert_eq!(storage.get(&id1).unwrap(), Some(key_share(3)));
+		assert_eq!(storage.iter().collect::<Vec<_>>(), vec![(id1, key_share(3)), (id2, key_share(2))]);
+
+		storage.remove(&id1).unwrap();
+		assert!(!storage.contains(&id1));
+		storage.clear().unwrap();
+		assert_eq!(storage.iter().count(), 0);
+	}
+
+	#[test]
+	fn key_shares_are_imported_on_first_open_only() {
+		let import_file = temp_path("import");
+		let shares: BTreeMap<String, serde_json::Value> = vec![(
+			format!("0x{}", hex::encode(ServerKeyId::from_low_u64_be(1).as_bytes())),
+			serde_json::from_slice(&serialize_key_share(key_share(1)).unwrap()).unwrap(),
+		)].into_iter().collect();
+		std::fs::write(&import_file, serde_json::to_vec(&shares).unwrap()).unwrap();
+
+		let db = Arc::new(MemoryDatabase::default());
+		let storage = PersistentKeyStorage::open(db.clone(), Some(&import_file)).unwrap();
+		assert_eq!(storage.get(&ServerKeyId::from_low_u64_be(1)).unwrap(), Some(key_share(1)));
+		assert_eq!(db.get_meta(VERSION_KEY).unwrap(), Some(vec![CURRENT_VERSION]));
+
+		storage.remove(&ServerKeyId::from_low_u64_be(1)).unwrap();
+		let storage = PersistentKeyStorage::open(db, Some(&import_file)).unwrap();
+		assert_eq!(storage.get(&ServerKeyId::from_low_u64_be(1)).unwrap(), None);
+
+		std::fs::remove_file(import_file).unwrap();
+	}
+
+	#[test]
+	fn invalid_import_leaves_database_uninitialized() {
+		let import_file = temp_path("invalid-import");
+		std::fs::write(&import_file, br#"{"0x01": {}}"#).unwrap();
+
+		let db = Arc::new(MemoryDatabase::default());
+		assert!(PersistentKeyStorage::open(db.clone(), Some(&import_file)).is_err());
+		assert_eq!(db.get_meta(VERSION_KEY).unwrap(), None);
+		assert!(db.values.lock().is_empty());
+
+		std::fs::remove_file(import_file).unwrap();
+	}
+
+	#[test]
+	fn unsupported_version_is_rejected() {
+		let db = MemoryDatabase::default();
+		db.write(vec![DatabaseOperation::InsertMeta(VERSION_KEY.to_vec(), vec![CURRENT_VERSION + 1])]).unwrap();
+		assert!(PersistentKeyStorage::open(db, None).is_err());
+	}
+
+	#[test]
+	fn key_shares_are_persisted_in_rocksdb() {
+		let path = temp_path("rocksdb");
+		let id = ServerKeyId::from_low_u64_be(1);
+		{
+			let storage = PersistentKeyStorage::open(RocksDbDatabase::open(&path).unwrap(), None).unwrap();
+			storage.insert(id, key_share(1)).unwrap();
+		}
+
+		let storage = PersistentKeyStorage::open(RocksDbDatabase::open(&path).unwrap(), None).unwrap();
+		assert_eq!(storage.get(&id).unwrap(), Some(key_share(1)));
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+}
@@ -0,0 +1,420 @@
+use std::{
+	num::NonZeroU32,
+	path::PathBuf,
+	sync::Arc,
+};
+use log::{error, info};
+use parking_lot::Mutex;
+use parity_crypto::Keccak256;
+use parity_secretstore_primitives::error::Error;
+use crate::key_storage::{Database, DatabaseOperation};
+
+/// Key of the passphrase salt record in the database metadata.
+const SALT_KEY: &'static [u8] = b"sealing_salt";
+/// Key of the database metadata record that is present once all values are sealed.
+/// After that, plain values are never accepted.
+const SEALED_KEY: &'static [u8] = b"sealed";
+/// Number of PBKDF2 iterations used to derive sealing key from passphrase.
+const PASSPHRASE_ITERATIONS: u32 = 10_240;
+/// Version byte that prefixes every sealed value.
+const SEALED_VALUE_VERSION: u8 = 1;
+/// Length of sealing key identifier.
+const KEY_ID_LEN: usize = 4;
+/// Length of AES initialization vector.
+const IV_LEN: usize = 16;
+/// Length of MAC.
+const MAC_LEN: usize = 32;
+/// Max number of records resealed under single lock.
+const RESEAL_BATCH_SIZE: usize = 64;
+
+/// Source of the sealing key.
+#[derive(Clone)]
+pub enum SealingKeySource {
+	/// Key is derived from passphrase that is read from given file.
+	PassphraseFile(PathBuf),
+	/// Hex-encoded 32-byte key is read from given file.
+	KeyFile(PathBuf),
+}
+
+/// Database wrapper that encrypts every stored value.
+///
+/// Values are sealed with the current key. Values sealed with any of previous keys
+/// are still readable and are resealed with the current key in background, so sealing
+/// key could be rotated by simply restarting with the new key and the old one marked
+/// as previous. Plain values, written before encryption has been enabled, are sealed
+/// once, when encrypted database is opened for the first time.
+pub struct EncryptedDatabase<D> {
+	/// Wrapped database.
+	db: D,
+	/// Key that is used to seal new values.
+	current_key: SealingKey,
+	/// Keys that are only used to unseal existing values.
+	previous_keys: Vec<SealingKey>,
+	/// Write lock that prevents concurrent writes from being overwritten by reseal.
+	write_lock: Mutex<()>,
+}
+
+/// Sealing key.
+struct SealingKey {
+	/// Key identifier (prefix of key hash).
+	id: [u8; KEY_ID_LEN],
+	/// Key itself.
+	key: [u8; 32],
+}
+
+impl<D: Database + 'static> EncryptedDatabase<D> {
+	/// Wrap database and start resealing values that are sealed with previous keys.
+	pub fn open(
+		db: D,
+		current_key: SealingKeySource,
+		previous_keys: Vec<SealingKeySource>,
+	) -> Result<Arc<Self>, Error> {
+		let current_key = SealingKey::load(&db, current_key)?;
+		let previous_keys = previous_keys.into_iter()
+			.map(|previous_key| SealingKey::load(&db, previous_key))
+			.collect::<Result<_, _>>()?;
+		let db = Arc::new(EncryptedDatabase::new(db, current_key, previous_keys)?);
+
+		let reseal_db = db.clone();
+		std::thread::Builder::new()
+			.name("key-storage-reseal".into())
+			.spawn(move || match reseal_db.reseal() {
+				Ok(0) => (),
+				Ok(resealed) => info!(
+					target: "secretstore",
+					"Resealed {} key shares with current sealing key. Previous sealing keys may now be removed",
+					resealed,
+				),
+				Err(error) => error!(
+					target: "secretstore",
+					"Failed to reseal key shares: {:?}",
+					error,
+				),
+			})
+			.map_err(|error| Error::Internal(format!("{}", error)))?;
+
+		Ok(db)
+	}
+
+	/// Wrap database, sealing all plain values if database has never been sealed before.
+	fn new(db: D, current_key: SealingKey, previous_keys: Vec<SealingKey>) -> Result<Self, Error> {
+		let db = EncryptedDatabase {
+			db,
+			current_key,
+			previous_keys,
+			write_lock: Mutex::new(()),
+		};
+		if db.db.get_meta(SEALED_KEY)?.is_none() {
+			db.seal_plain_values()?;
+		}
+		Ok(db)
+	}
+
+	/// Seal all plain values and mark database as sealed, in a single batch.
+	fn seal_plain_values(&self) -> Result<(), Error> {
+		let _write_lock = self.write_lock.lock();
+		let mut operations = Vec::new();
+		for (key, value) in self.db.iter() {
+			if value.first() != Some(&b'{') {
+				continue;
+			}
+
+			operations.push(DatabaseOperation::Insert(key.clone(), self.seal(&key, &value)?));
+		}
+
+		info!(
+			target: "secretstore",
+			"Enabling key storage encryption. Sealing {} plain key shares",
+			operations.len(),
+		);
+
+		operations.push(DatabaseOperation::InsertMeta(SEALED_KEY.to_vec(), vec![1]));
+		self.db.write(operations)
+	}
+
+	/// Reseal all values that are not sealed with current key.
+	fn reseal(&self) -> Result<usize, Error> {
+		let outdated_keys = self.db.iter()
+			.filter(|(_, value)| !self.is_sealed_with_current_key(value))
+			.map(|(key, _)| key)
+			.collect::<Vec<_>>();
+
+		let mut resealed = 0;
+		for batch in outdated_keys.chunks(RESEAL_BATCH_SIZE) {
+			let _write_lock = self.write_lock.lock();
+			let mut operations = Vec::with_capacity(batch.len());
+			for key in batch {
+				// value could have been updated or removed since we have read it
+				let value = match self.db.get(key)? {
+					Some(ref value) if !self.is_sealed_with_current_key(value) => self.unseal(key, value)?,
+					_ => continue,
+				};
+				operations.push(DatabaseOperation::Insert(key.clone(), self.seal(key, &value)?));
+			}
+			resealed += operations.len();
+			self.db.write(operations)?;
+		}
+
+		Ok(resealed)
+	}
+
+	/// Returns true if value is sealed with current key.
+	fn is_sealed_with_current_key(&self, value: &[u8]) -> bool {
+		value.len() > 1 + KEY_ID_LEN
+			&& value[0] == SEALED_VALUE_VERSION
+			&& value[1..1 + KEY_ID_LEN] == self.current_key.id
+	}
+}
+
+impl<D> EncryptedDatabase<D> {
+	/// Seal value of given record with current key.
+	fn seal(&self, record_key: &[u8], value: &[u8]) -> Result<Vec<u8>, Error> {
+		let iv: [u8; IV_LEN] = rand::random();
+		let mut sealed = Vec::with_capacity(1 + KEY_ID_LEN + IV_LEN + value.len() + MAC_LEN);
+		sealed.push(SEALED_VALUE_VERSION);
+		sealed.extend_from_slice(&self.current_key.id);
+		sealed.extend_from_slice(&iv);
+		sealed.resize(1 + KEY_ID_LEN + IV_LEN + value.len(), 0);
+		parity_crypto::aes::encrypt_128_ctr(&self.current_key.key[..16], &iv, value, &mut sealed[1 + KEY_ID_LEN + IV_LEN..])
+			.map_err(|error| Error::Internal(format!("{}", error)))?;
+		let mac = mac(&self.current_key, record_key, &sealed);
+		sealed.extend_from_slice(&mac);
+		Ok(sealed)
+	}
+
+	/// Unseal value of given record with one of known keys.
+	fn unseal(&self, record_key: &[u8], value: &[u8]) -> Result<Vec<u8>, Error> {
+		if value.len() < 1 + KEY_ID_LEN + IV_LEN + MAC_LEN || value[0] != SEALED_VALUE_VERSION {
+			return Err(Error::Database("Invalid sealed value".into()));
+		}
+
+		let key_id = &value[1..1 + KEY_ID_LEN];
+		let key = std::iter::once(&self.current_key)
+			.chain(self.previous_keys.iter())
+			.find(|key| key.id == key_id)
+			.ok_or_else(|| Error::Database(format!("Value is sealed with unknown key {}", hex::encode(key_id))))?;
+		let iv = &value[1 + KEY_ID_LEN..1 + KEY_ID_LEN + IV_LEN];
+		let cipher_text = &value[1 + KEY_ID_LEN + IV_LEN..value.len() - MAC_LEN];
+		let mac = &value[value.len() - MAC_LEN..];
+		let expected_mac = self::mac(key, record_key, &value[..value.len() - MAC_LEN]);
+		if !parity_crypto::is_equal(&expected_mac, mac) {
+			return Err(Error::Database("Sealed value MAC mismatch".into()));
+		}
+
+		let mut plain = vec![0u8; cipher_text.len()];
+		parity_crypto::aes::decrypt_128_ctr(&key.key[..16], iv, cipher_text, &mut plain)
+			.map_err(|error| Error::Internal(format!("{}", error)))?;
+		Ok(plain)
+	}
+}
+
+impl<D: Database> Database for EncryptedDatabase<D> {
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.db.get(key)?
+			.map(|value| self.unseal(key, &value))
+			.transpose()
+	}
+
+	fn get_meta(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.db.get_meta(key)
+	}
+
+	fn write(&self, operations: Vec<DatabaseOperation>) -> Result<(), Error> {
+		let operations = operations.into_iter()
+			.map(|operation| match operation {
+				DatabaseOperation::Insert(key, value) => self.seal(&key, &value)
+					.map(|value| DatabaseOperation::Insert(key, value)),
+				DatabaseOperation::Delete(key) => Ok(DatabaseOperation::Delete(key)),
+				DatabaseOperation::InsertMeta(key, value) => Ok(DatabaseOperation::InsertMeta(key, value)),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		let _write_lock = self.write_lock.lock();
+		self.db.write(operations)
+	}
+
+	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+		Box::new(self.db.iter()
+			.filter_map(move |(key, value)| match self.unseal(&key, &value) {
+				Ok(value) => Some((key, value)),
+				Err(error) => {
+					error!(
+						target: "secretstore",
+						"Failed to unseal value {}: {:?}",
+						hex::encode(&key),
+						error,
+					);
+
+					None
+				},
+			}))
+	}
+}
+
+impl SealingKey {
+	/// Load sealing key from given source.
+	fn load(db: &dyn Database, source: SealingKeySource) -> Result<Self, Error> {
+		let key = match source {
+			SealingKeySource::PassphraseFile(path) => {
+				let passphrase = std::fs::read_to_string(&path)
+					.map_err(|error| Error::Internal(format!("Failed to read {}: {}", path.display(), error)))?;
+				let salt = passphrase_salt(db)?;
+				let (left, right) = parity_crypto::derive_key_iterations(
+					passphrase.trim_end_matches(&['\r', '\n'][..]).as_bytes(),
+					&salt,
+					NonZeroU32::new(PASSPHRASE_ITERATIONS).expect("PASSPHRASE_ITERATIONS is non-zero; qed"),
+				);
+				let mut key = [0u8; 32];
+				key[..16].copy_from_slice(&left);
+				key[16..].copy_from_slice(&right);
+				key
+			},
+			SealingKeySource::KeyFile(path) => {
+				let key_hex = std::fs::read_to_string(&path)
+					.map_err(|error| Error::Internal(format!("Failed to read {}: {}", path.display(), error)))?;
+				let mut key = [0u8; 32];
+				hex::decode_to_slice(key_hex.trim().trim_start_matches("0x"), &mut key)
+					.map_err(|error| Error::Internal(format!("Invalid sealing key in {}: {}", path.display(), error)))?;
+				key
+			},
+		};
+
+		Ok(SealingKey::new(key))
+	}
+
+	/// Create sealing key from raw key bytes.
+	fn new(key: [u8; 32]) -> Self {
+		let mut id = [0u8; KEY_ID_LEN];
+		id.copy_from_slice(&key.keccak256()[..KEY_ID_LEN]);
+		SealingKey {
+			id,
+			key,
+		}
+	}
+}
+
+/// Compute MAC of sealed value header and cipher text, bound to the record key, so
+/// that sealed values can't be moved between records.
+fn mac(key: &SealingKey, record_key: &[u8], sealed_without_mac: &[u8]) -> [u8; MAC_LEN] {
+	let mut authenticated = Vec::with_capacity(4 + record_key.len() + sealed_without_mac.len());
+	authenticated.extend_from_slice(&(record_key.len() as u32).to_le_bytes());
+	authenticated.extend_from_slice(record_key);
+	authenticated.extend_from_slice(sealed_without_mac);
+	parity_crypto::derive_mac(&key.key[16..], &authenticated).keccak256()
+}
+
+/// Returns true if values of the database have been sealed.
+pub fn is_sealed(db: &dyn Database) -> Result<bool, Error> {
+	Ok(db.get_meta(SEALED_KEY)?.is_some())
+}
+
+/// Read passphrase salt from the database metadata, generating new salt if required.
+fn passphrase_salt(db: &dyn Database) -> Result<Vec<u8>, Error> {
+	if let Some(salt) = db.get_meta(SALT_KEY)? {
+		return Ok(salt);
+	}
+
+	let salt: [u8; 32] = rand::random();
+	db.write(vec![DatabaseOperation::InsertMeta(SALT_KEY.to_vec(), salt.to_vec())])?;
+	Ok(salt.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use parity_secretstore_primitives::{ServerKeyId, key_storage::KeyStorage};
+	use crate::key_storage::{
+		PersistentKeyStorage,
+		tests::{MemoryDatabase, key_share},
+	};
+	use super::*;
+
+	fn open(db: Arc<MemoryDatabase>, current_key: u8, previous_keys: &[u8]) -> EncryptedDatabase<Arc<MemoryDatabase>> {
+		EncryptedDatabase::new(
+			db,
+			SealingKey::new([current_key; 32]),
+			previous_keys.iter().map(|key| SealingKey::new([*key; 32])).collect(),
+		).unwrap()
+	}
+
+	#[test]
+	fn sealed_value_is_unsealed() {
+		let db = Arc::new(MemoryDatabase::default());
+		let encrypted = open(db.clone(), 1, &[]);
+		encrypted.write(vec![DatabaseOperation::Insert(vec![1; 32], b"{\"value\":1}".to_vec())]).unwrap();
+
+		assert_ne!(db.get(&[1; 32]).unwrap(), Some(b"{\"value\":1}".to_vec()));
+		assert_eq!(encrypted.get(&[1; 32]).unwrap(), Some(b"{\"value\":1}".to_vec()));
+		assert_eq!(encrypted.iter().collect::<Vec<_>>(), vec![(vec![1; 32], b"{\"value\":1}".to_vec())]);
+	}
+
+	#[test]
+	fn key_storage_works_on_top_of_encrypted_database() {
+		let db = Arc::new(MemoryDatabase::default());
+		let storage = PersistentKeyStorage::open(open(db, 1, &[]), None).unwrap();
+		storage.insert(ServerKeyId::from_low_u64_be(1), key_share(1)).unwrap();
+		assert_eq!(storage.get(&ServerKeyId::from_low_u64_be(1)).unwrap(), Some(key_share(1)));
+	}
+
+	#[test]
+	fn sealing_key_is_rotated() {
+		let db = Arc::new(MemoryDatabase::default());
+		open(db.clone(), 1, &[]).write(vec![DatabaseOperation::Insert(vec![1; 32], b"{}".to_vec())]).unwrap();
+
+		// new key can't unseal value without previous key
+		assert!(open(db.clone(), 2, &[]).get(&[1; 32]).is_err());
+
+		let encrypted = open(db.clone(), 2, &[1]);
+		assert_eq!(encrypted.get(&[1; 32]).unwrap(), Some(b"{}".to_vec()));
+		assert_eq!(encrypted.reseal().unwrap(), 1);
+		assert_eq!(encrypted.reseal().unwrap(), 0);
+
+		// previous key is not required anymore
+		assert_eq!(open(db, 2, &[]).get(&[1; 32]).unwrap(), Some(b"{}".to_vec()));
+	}
+
+	#[test]
+	fn tampered_value_is_rejected() {
+		let db = Arc::new(MemoryDatabase::default());
+		let encrypted = open(db.clone(), 1, &[]);
+		encrypted.write(vec![
+			DatabaseOperation::Insert(vec![1; 32], b"{\"value\":1}".to_vec()),
+			DatabaseOperation::Insert(vec![2; 32], b"{\"value\":2}".to_vec()),
+		]).unwrap();
+		let sealed = db.get(&[1; 32]).unwrap().unwrap();
+
+		// every byte of sealed value is authenticated
+		for index in 0..sealed.len() {
+			let mut tampered = sealed.clone();
+			tampered[index] ^= 1;
+			db.write(vec![DatabaseOperation::Insert(vec![1; 32], tampered)]).unwrap();
+			assert!(encrypted.get(&[1; 32]).is_err(), "byte {} is not authenticated", index);
+		}
+
+		// sealed value can't be moved to other record
+		db.write(vec![DatabaseOperation::Insert(vec![2; 32], sealed)]).unwrap();
+		assert!(encrypted.get(&[2; 32]).is_err());
+	}
+
+	#[test]
+	fn plain_values_are_only_accepted_during_migration() {
+		let db = Arc::new(MemoryDatabase::default());
+		db.write(vec![DatabaseOperation::Insert(vec![1; 32], b"{\"value\":1}".to_vec())]).unwrap();
+
+		let encrypted = open(db.clone(), 1, &[]);
+		assert_ne!(db.get(&[1; 32]).unwrap(), Some(b"{\"value\":1}".to_vec()));
+		assert_eq!(encrypted.get(&[1; 32]).unwrap(), Some(b"{\"value\":1}".to_vec()));
+
+		db.write(vec![DatabaseOperation::Insert(vec![2; 32], b"{\"value\":2}".to_vec())]).unwrap();
+		let encrypted = open(db.clone(), 1, &[]);
+		assert!(encrypted.get(&[2; 32]).is_err());
+		assert_eq!(encrypted.iter().count(), 1);
+	}
+	#[test]
+	fn database_is_marked_sealed() {
+		let db = Arc::new(MemoryDatabase::default());
+		assert!(!is_sealed(&*db).unwrap());
+
+		open(db.clone(), 1, &[]);
+		assert!(is_sealed(&*db).unwrap());
+	}
+}
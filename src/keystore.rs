@@ -0,0 +1,386 @@
+use std::{
+	num::NonZeroU32,
+	path::{Path, PathBuf},
+};
+use parity_crypto::{
+	Keccak256,
+	publickey::{KeyPair, Secret},
+};
+use serde::{Deserialize, Serialize};
+
+/// Default scrypt N parameter for new key files.
+const SCRYPT_N: u32 = 8192;
+/// Default scrypt r parameter for new key files.
+const SCRYPT_R: u32 = 8;
+/// Default scrypt p parameter for new key files.
+const SCRYPT_P: u32 = 1;
+/// Length of derived key.
+const DKLEN: u32 = 32;
+
+/// All possible errors that can occur when working with key files.
+#[derive(Debug)]
+pub enum Error {
+	/// Failed to read or write file.
+	Io(PathBuf, std::io::Error),
+	/// Failed to (de)serialize key file.
+	Json(serde_json::Error),
+	/// Key file has unsupported format.
+	UnsupportedFormat(String),
+	/// Key file is corrupted.
+	InvalidKeyFile(String),
+	/// Password is invalid.
+	InvalidPassword,
+}
+
+/// Ethereum v3 key file.
+#[derive(Serialize, Deserialize)]
+pub struct KeyFile {
+	/// Key file identifier.
+	pub id: String,
+	/// Key file version.
+	pub version: u32,
+	/// Encrypted secret.
+	pub crypto: Crypto,
+	/// Address of the key (hex-encoded without 0x prefix).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub address: Option<String>,
+}
+
+/// Encrypted secret.
+#[derive(Serialize, Deserialize)]
+pub struct Crypto {
+	/// Cipher name.
+	pub cipher: String,
+	/// Cipher parameters.
+	pub cipherparams: CipherParams,
+	/// Hex-encoded encrypted secret.
+	pub ciphertext: String,
+	/// Key derivation function name.
+	pub kdf: String,
+	/// Key derivation function parameters.
+	pub kdfparams: KdfParams,
+	/// Hex-encoded MAC.
+	pub mac: String,
+}
+
+/// Cipher parameters.
+#[derive(Serialize, Deserialize)]
+pub struct CipherParams {
+	/// Hex-encoded initialization vector.
+	pub iv: String,
+}
+
+/// Key derivation function parameters.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+	/// Scrypt parameters.
+	Scrypt {
+		/// Derived key length.
+		dklen: u32,
+		/// Hex-encoded salt.
+		salt: String,
+		/// CPU/memory cost.
+		n: u32,
+		/// Block size.
+		r: u32,
+		/// Parallelization.
+		p: u32,
+	},
+	/// PBKDF2 parameters.
+	Pbkdf2 {
+		/// Iterations count.
+		c: u32,
+		/// Derived key length.
+		dklen: u32,
+		/// Pseudo-random function name.
+		prf: String,
+		/// Hex-encoded salt.
+		salt: String,
+	},
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match *self {
+			Error::Io(ref path, ref error) => write!(f, "failed to access {}: {}", path.display(), error),
+			Error::Json(ref error) => write!(f, "invalid key file: {}", error),
+			Error::UnsupportedFormat(ref error) => write!(f, "unsupported key file: {}", error),
+			Error::InvalidKeyFile(ref error) => write!(f, "invalid key file: {}", error),
+			Error::InvalidPassword => write!(f, "invalid password"),
+		}
+	}
+}
+
+impl KeyFile {
+	/// Read key file.
+	pub fn read(path: &Path) -> Result<Self, Error> {
+		let contents = std::fs::read(path).map_err(|error| Error::Io(path.into(), error))?;
+		let key_file: KeyFile = serde_json::from_slice(&contents).map_err(Error::Json)?;
+		if key_file.version != 3 {
+			return Err(Error::UnsupportedFormat(format!("version {}", key_file.version)));
+		}
+		Ok(key_file)
+	}
+
+	/// Write key file. Fails if file already exists.
+	pub fn write(&self, path: &Path) -> Result<(), Error> {
+		use std::io::Write;
+
+		let contents = serde_json::to_vec_pretty(self).map_err(Error::Json)?;
+		let mut options = std::fs::OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::OpenOptionsExt;
+			options.mode(0o600);
+		}
+		options.open(path)
+			.and_then(|mut file| file.write_all(&contents))
+			.map_err(|error| Error::Io(path.into(), error))
+	}
+
+	/// Encrypt key pair secret with given password.
+	pub fn encrypt(key_pair: &KeyPair, password: &[u8]) -> Result<Self, Error> {
+		let salt: [u8; 32] = rand::random();
+		let iv: [u8; 16] = rand::random();
+		let (derived_left, derived_right) = parity_crypto::scrypt::derive_key(password, &salt, SCRYPT_N, SCRYPT_P, SCRYPT_R)
+			.map_err(|error| Error::InvalidKeyFile(format!("{}", error)))?;
+		let mut ciphertext = [0u8; 32];
+		parity_crypto::aes::encrypt_128_ctr(&derived_left, &iv, key_pair.secret().as_bytes(), &mut ciphertext)
+			.map_err(|error| Error::InvalidKeyFile(format!("{}", error)))?;
+		let mac = parity_crypto::derive_mac(&derived_right, &ciphertext).keccak256();
+
+		Ok(KeyFile {
+			id: random_uuid(),
+			version: 3,
+			crypto: Crypto {
+				cipher: "aes-128-ctr".into(),
+				cipherparams: CipherParams {
+					iv: hex::encode(iv),
+				},
+				ciphertext: hex::encode(ciphertext),
+				kdf: "scrypt".into(),
+				kdfparams: KdfParams::Scrypt {
+					dklen: DKLEN,
+					salt: hex::encode(salt),
+					n: SCRYPT_N,
+					r: SCRYPT_R,
+					p: SCRYPT_P,
+				},
+				mac: hex::encode(mac),
+			},
+			address: Some(hex::encode(key_pair.address().as_bytes())),
+		})
+	}
+
+	/// Decrypt key pair with given password.
+	pub fn decrypt(&self, password: &[u8]) -> Result<KeyPair, Error> {
+		if self.crypto.cipher != "aes-128-ctr" {
+			return Err(Error::UnsupportedFormat(format!("cipher {}", self.crypto.cipher)));
+		}
+
+		let (derived_left, derived_right) = match self.crypto.kdfparams {
+			KdfParams::Scrypt { dklen, ref salt, n, r, p } if self.crypto.kdf == "scrypt" => {
+				check_dklen(dklen)?;
+				parity_crypto::scrypt::derive_key(password, &decode_hex(salt)?, n, p, r)
+					.map_err(|error| Error::InvalidKeyFile(format!("{}", error)))?
+			},
+			KdfParams::Pbkdf2 { c, dklen, ref prf, ref salt } if self.crypto.kdf == "pbkdf2" => {
+				check_dklen(dklen)?;
+				if prf != "hmac-sha256" {
+					return Err(Error::UnsupportedFormat(format!("prf {}", prf)));
+				}
+				let c = NonZeroU32::new(c)
+					.ok_or_else(|| Error::InvalidKeyFile("zero iterations count".into()))?;
+				parity_crypto::derive_key_iterations(password, &decode_hex(salt)?, c)
+			},
+			_ => return Err(Error::UnsupportedFormat(format!("kdf {}", self.crypto.kdf))),
+		};
+
+		let ciphertext = decode_hex(&self.crypto.ciphertext)?;
+		let mac = parity_crypto::derive_mac(&derived_right, &ciphertext).keccak256();
+		if !parity_crypto::is_equal(&mac, &decode_hex(&self.crypto.mac)?) {
+			return Err(Error::InvalidPassword);
+		}
+
+		let iv = decode_hex(&self.crypto.cipherparams.iv)?;
+		let mut secret = [0u8; 32];
+		if ciphertext.len() != secret.len() {
+			return Err(Error::InvalidKeyFile("invalid ciphertext length".into()));
+		}
+		parity_crypto::aes::decrypt_128_ctr(&derived_left, &iv, &ciphertext, &mut secret)
+			.map_err(|error| Error::InvalidKeyFile(format!("{}", error)))?;
+		let secret = Secret::copy_from_slice(&secret)
+			.ok_or_else(|| Error::InvalidKeyFile("invalid secret".into()))?;
+		KeyPair::from_secret(secret).map_err(|error| Error::InvalidKeyFile(format!("{}", error)))
+	}
+}
+
+/// Generate new key file, encrypted with password from the password file.
+pub fn generate(path: &Path, password_file: &Path, secret_file: Option<&Path>) -> Result<(), Error> {
+	let password = read_password(password_file)?;
+	let key_pair = match secret_file {
+		Some(secret_file) => {
+			let secret = std::fs::read_to_string(secret_file)
+				.map_err(|error| Error::Io(secret_file.into(), error))?;
+			let secret = decode_hex(secret.trim().trim_start_matches("0x"))?;
+			let secret = Secret::copy_from_slice(&secret)
+				.ok_or_else(|| Error::InvalidKeyFile("invalid secret".into()))?;
+			KeyPair::from_secret(secret).map_err(|error| Error::InvalidKeyFile(format!("{}", error)))?
+		},
+		None => random_key_pair(),
+	};
+
+	KeyFile::encrypt(&key_pair, password.as_bytes())?.write(path)?;
+	print_key_pair_info(&key_pair);
+	Ok(())
+}
+
+/// Print key file information. If password file is specified, key file is decrypted.
+pub fn inspect(path: &Path, password_file: Option<&Path>) -> Result<(), Error> {
+	let key_file = KeyFile::read(path)?;
+	println!("Id: {}", key_file.id);
+	println!("KDF: {}", key_file.crypto.kdf);
+	if let Some(ref address) = key_file.address {
+		println!("Stored address: 0x{}", address);
+	}
+
+	if let Some(password_file) = password_file {
+		let password = read_password(password_file)?;
+		print_key_pair_info(&key_file.decrypt(password.as_bytes())?);
+	}
+
+	Ok(())
+}
+
+/// Read key file and decrypt key pair using password from the password file.
+pub fn load_key_pair(path: &Path, password_file: &Path) -> Result<KeyPair, Error> {
+	let password = read_password(password_file)?;
+	KeyFile::read(path)?.decrypt(password.as_bytes())
+}
+
+/// Read password from the file.
+pub fn read_password(path: &Path) -> Result<String, Error> {
+	std::fs::read_to_string(path)
+		.map(|password| password.trim_end_matches(&['\r', '\n'][..]).to_owned())
+		.map_err(|error| Error::Io(path.into(), error))
+}
+
+/// Print public information about key pair.
+fn print_key_pair_info(key_pair: &KeyPair) {
+	println!("Address: 0x{}", hex::encode(key_pair.address().as_bytes()));
+	println!("Public: 0x{}", hex::encode(key_pair.public().as_bytes()));
+}
+
+/// Check that derived key length is supported.
+fn check_dklen(dklen: u32) -> Result<(), Error> {
+	if dklen != DKLEN {
+		return Err(Error::UnsupportedFormat(format!("dklen {}", dklen)));
+	}
+	Ok(())
+}
+
+/// Decode hex string.
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+	hex::decode(value).map_err(|error| Error::InvalidKeyFile(format!("{}", error)))
+}
+
+/// Generate random key pair.
+fn random_key_pair() -> KeyPair {
+	loop {
+		let secret: [u8; 32] = rand::random();
+		if let Some(key_pair) = Secret::copy_from_slice(&secret).and_then(|secret| KeyPair::from_secret(secret).ok()) {
+			return key_pair;
+		}
+	}
+}
+
+/// Generate random (v4) UUID.
+fn random_uuid() -> String {
+	let mut bytes: [u8; 16] = rand::random();
+	bytes[6] = (bytes[6] & 0x0f) | 0x40;
+	bytes[8] = (bytes[8] & 0x3f) | 0x80;
+	let bytes = hex::encode(bytes);
+	format!("{}-{}-{}-{}-{}", &bytes[0..8], &bytes[8..12], &bytes[12..16], &bytes[16..20], &bytes[20..32])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// PBKDF2 test vector from the Web3 Secret Storage Definition.
+	const PBKDF2_KEY_FILE: &'static str = r#"{
+		"crypto": {
+			"cipher": "aes-128-ctr",
+			"cipherparams": {
+				"iv": "6087dab2f9fdbbfaddc31a909735c1e6"
+			},
+			"ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+			"kdf": "pbkdf2",
+			"kdfparams": {
+				"c": 262144,
+				"dklen": 32,
+				"prf": "hmac-sha256",
+				"salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+			},
+			"mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+		},
+		"id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+		"version": 3
+	}"#;
+	/// Secret of the PBKDF2 test vector.
+	const PBKDF2_SECRET: &'static str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+	#[test]
+	fn pbkdf2_key_file_is_decrypted() {
+		let key_file: KeyFile = serde_json::from_str(PBKDF2_KEY_FILE).unwrap();
+		let key_pair = key_file.decrypt(b"testpassword").unwrap();
+
+		assert_eq!(hex::encode(key_pair.secret().as_bytes()), PBKDF2_SECRET);
+	}
+
+	#[test]
+	fn encrypted_key_pair_is_decrypted() {
+		let key_pair = random_key_pair();
+		let key_file = KeyFile::encrypt(&key_pair, b"password").unwrap();
+		let key_file: KeyFile = serde_json::from_slice(&serde_json::to_vec(&key_file).unwrap()).unwrap();
+
+		assert_eq!(key_file.version, 3);
+		assert_eq!(key_file.crypto.kdf, "scrypt");
+		assert_eq!(key_file.address, Some(hex::encode(key_pair.address().as_bytes())));
+		assert_eq!(key_file.decrypt(b"password").unwrap().secret(), key_pair.secret());
+	}
+
+	#[test]
+	fn key_file_is_not_decrypted_with_wrong_password() {
+		let key_file = KeyFile::encrypt(&random_key_pair(), b"password").unwrap();
+		match key_file.decrypt(b"wrong password") {
+			Err(Error::InvalidPassword) => (),
+			result => panic!("unexpected result: {:?}", result.map(|key_pair| key_pair.address())),
+		}
+	}
+
+	#[test]
+	fn tampered_key_file_is_not_decrypted() {
+		let mut key_file = KeyFile::encrypt(&random_key_pair(), b"password").unwrap();
+		let mut ciphertext = hex::decode(&key_file.crypto.ciphertext).unwrap();
+		ciphertext[0] ^= 1;
+		key_file.crypto.ciphertext = hex::encode(ciphertext);
+
+		match key_file.decrypt(b"password") {
+			Err(Error::InvalidPassword) => (),
+			result => panic!("unexpected result: {:?}", result.map(|key_pair| key_pair.address())),
+		}
+	}
+
+	#[test]
+	fn key_file_with_unsupported_parameters_is_not_decrypted() {
+		let mut key_file: KeyFile = serde_json::from_str(PBKDF2_KEY_FILE).unwrap();
+		key_file.crypto.cipher = "aes-256-cbc".into();
+		match key_file.decrypt(b"testpassword") {
+			Err(Error::UnsupportedFormat(_)) => (),
+			result => panic!("unexpected result: {:?}", result.map(|key_pair| key_pair.address())),
+		}
+	}
+}
@@ -1,90 +1,248 @@
 mod acl_storage;
+mod admin;
+mod address_resolver;
+mod async_bridge;
+mod atomic_file;
+mod best_block_tracker;
+mod checkpoint;
 mod blockchain;
+mod configuration;
+mod event_arguments;
+mod fee_policy;
 mod key_server_set;
+mod key_storage;
+mod key_storage_encryption;
+mod keystore;
+mod metadata;
+mod nonce_tracker;
 mod runtime;
 mod secret_store;
 mod service;
+mod signer;
 mod substrate_client;
 mod transaction_pool;
 
 use std::{
-	collections::VecDeque,
 	io::Write,
+	pin::Pin,
 	sync::Arc,
 };
-use futures::future::FutureExt;
+use futures::{future::FutureExt, stream::{Stream, StreamExt}};
 use log::error;
-use parity_crypto::publickey::KeyPair;
 use parity_secretstore_primitives::{
-	KeyServerId,
 	executor::tokio_runtime,
+	key_server_key_pair::InMemoryKeyServerKeyPair,
 };
 
 
 fn main() {
 	initialize();
 
+	let config = match configuration::parse() {
+		Ok(configuration::Command::Run(config)) => config,
+		Ok(configuration::Command::GenerateKeystore { path, password_file, secret_file }) => {
+			exit_on_keystore_error(keystore::generate(&path, &password_file, secret_file.as_ref().map(|path| path.as_path())));
+			return;
+		},
+		Ok(configuration::Command::InspectKeystore { path, password_file }) => {
+			exit_on_keystore_error(keystore::inspect(&path, password_file.as_ref().map(|path| path.as_path())));
+			return;
+		},
+		Err(error) => {
+			error!(
+				target: "secretstore",
+				"Invalid configuration: {}",
+				error,
+			);
+
+			std::process::exit(1);
+		},
+	};
+
 	let mut local_pool = futures::executor::LocalPool::new();
 	local_pool.run_until(async move {
 		// we still need tokio 0.1 runtime to run SS :/
 		let tokio_runtime = tokio_runtime().unwrap();
 
-		let uri = format!("{}:{}", "localhost", 11011);
-		let self_id = KeyServerId::default();
-		let client = substrate_client::Client::new(&uri, sp_keyring::AccountKeyring::Alice.pair()).await.unwrap();
+		let self_id = config.key_server.key_pair.address();
+		let client = substrate_client::Client::new(
+			config.substrate.endpoints,
+			config.signer.signer,
+			config.substrate.transaction_era,
+			config.substrate.fee_policy,
+		).await.unwrap();
+
+		let bridge = async_bridge::AsyncBridge::new().unwrap();
+		let key_server_key_pair = Arc::new(InMemoryKeyServerKeyPair::new(config.key_server.key_pair.clone()));
+		let acl_storage = Arc::new(crate::acl_storage::OnChainAclStorage::new(client.clone(), bridge.clone()));
+		let key_server_set = Arc::new(crate::key_server_set::OnChainKeyServerSet::new(
+			client.clone(),
+			bridge.clone(),
+			self_id.clone(),
+			config.key_server.migration_state_file.clone(),
+		));
+
+		if let Err(error) = admin::start(config.admin, key_server_set.clone(), acl_storage.clone()) {
+			error!(
+				target: "secretstore",
+				"Failed to start admin endpoint: {}",
+				error,
+			);
+
+			std::process::exit(1);
+		}
 
-		let acl_storage = Arc::new(crate::acl_storage::OnChainAclStorage::new(client.clone()));
-		let key_server_set = Arc::new(crate::key_server_set::OnChainKeyServerSet::new(client.clone(), self_id.clone()));
-		//let service = Arc::new(crate::service::OnChainService::new(client.clone(), self_id.clone()));
 		let key_server = secret_store::start(
 			tokio_runtime.executor(),
-			KeyPair::from_secret([1u8; 32].into()).unwrap(),
-			10_000u16,
+			key_server_key_pair.clone(),
+			config.key_server,
+			config.key_storage,
 			acl_storage.clone(),
 			key_server_set.clone(),
 		).unwrap();
 
-		let mut finalized_headers = VecDeque::new();
-		let mut finalized_header_events_retrieval_active = false;
+		let checkpoint = config.service.checkpoint_file.as_ref()
+			.and_then(|path| checkpoint::Checkpoint::new(path.clone()).read());
+		let replay = match checkpoint::replay_start(&client, checkpoint, config.service.max_catch_up_blocks).await {
+			Ok(replay) => replay,
+			Err(error) => {
+				error!(
+					target: "secretstore",
+					"Failed to select blocks to replay: {:?}",
+					error,
+				);
+
+				std::process::exit(1);
+			},
+		};
+		let last_processed_number = replay.map(|(last_processed_number, _)| last_processed_number);
+		let last_replayed_number = replay.map(|(_, last_replayed_number)| last_replayed_number);
+		let (new_blocks_sender, new_blocks_receiver) = futures::channel::mpsc::unbounded();
+		let fut_service = service::start(
+			client.clone(),
+			bridge,
+			tokio_runtime.executor(),
+			key_server,
+			key_server_key_pair,
+			new_blocks_receiver,
+			config.service,
+		).fuse();
 
-		let mut fut_finalized_headers = client.subscribe_finalized_heads().await.unwrap();
-		let fut_finalized_header_events = futures::future::Fuse::terminated();
+		let fut_health = client.clone().monitor_health().fuse();
+		let finalized_headers = client.finalized_headers(last_processed_number).fuse();
+		let mut best_block_tracker = config.substrate.best_block_confirmations
+			.map(|confirmations| best_block_tracker::BestBlockTracker::new(client.clone(), confirmations));
+		let new_headers: Pin<Box<dyn Stream<Item = runtime::Header>>> = match best_block_tracker {
+			Some(_) => Box::pin(client.new_headers()),
+			None => Box::pin(futures::stream::pending()),
+		};
+		let mut new_headers = new_headers.fuse();
 
 		futures::pin_mut!(
-			fut_finalized_header_events
+			finalized_headers,
+			fut_service,
+			fut_health
 		);
 
 		loop {
 			futures::select! {
-				finalized_header = fut_finalized_headers.next().fuse() => {
+				finalized_header = finalized_headers.select_next_some() => {
 					let finalized_header_hash = finalized_header.hash();
-					finalized_headers.push_back((finalized_header.number, finalized_header_hash));
-					acl_storage.set_best_block((finalized_header.number, finalized_header_hash));
-					key_server_set.set_best_block((finalized_header.number, finalized_header_hash));
-					//service.set_best_block((finalized_header.number, finalized_header_hash));
-				},
-				finalized_header_events = fut_finalized_header_events => {
-					/*match finalized_header_events {
-						Ok(finalized_header_events) => service.append_block_events(finalized_header_events),
-						Err(error) => error!(
-							target: "secretstore_net",
-							"Error reading Substrate header events: {:?}",
+					// replayed blocks are only passed to the service, everything else is tracked
+					// starting from the finalized head
+					let is_replayed_block = last_replayed_number
+						.map(|last_replayed_number| finalized_header.number < last_replayed_number)
+						.unwrap_or(false);
+					if !is_replayed_block {
+						if let Err(error) = client.refresh_runtime_version(finalized_header_hash).await {
+							error!(
+								target: "secretstore",
+								"Failed to read runtime version at block {}: {:?}",
+								finalized_header_hash,
+								error,
+							);
+						}
+						if let Err(error) = client.check_account_index_gap().await {
+							error!(
+								target: "secretstore",
+								"Failed to check signer account index: {:?}",
+								error,
+							);
+						}
+						match best_block_tracker {
+							Some(ref mut best_block_tracker) => best_block_tracker.on_finalized(finalized_header.number),
+							None => {
+								acl_storage.set_best_block((finalized_header.number, finalized_header_hash));
+								key_server_set.set_best_block((finalized_header.number, finalized_header_hash)).await;
+							},
+						}
+					}
+					if let Err(error) = new_blocks_sender.unbounded_send((finalized_header.number, finalized_header_hash)) {
+						error!(
+							target: "secretstore",
+							"Failed to notify service about new finalized block: {:?}",
 							error,
-						),
-					}*/
+						);
+					}
 				},
-			}
+				new_header = new_headers.select_next_some() => {
+					let best_block_tracker = match best_block_tracker {
+						Some(ref mut best_block_tracker) => best_block_tracker,
+						None => continue,
+					};
 
-			if !finalized_header_events_retrieval_active {
-				if let Some((_, finalized_header_hash)) = finalized_headers.pop_front() {
-					finalized_header_events_retrieval_active = true;
-					fut_finalized_header_events.set(client.header_events(finalized_header_hash).fuse());
-				}
+					let best_block_change = match best_block_tracker.on_new_head(new_header).await {
+						Ok(Some(best_block_change)) => best_block_change,
+						Ok(None) => continue,
+						Err(error) => {
+							error!(
+								target: "secretstore",
+								"Failed to process new best header: {:?}",
+								error,
+							);
+
+							continue;
+						},
+					};
+
+					// service only processes finalized blocks, so its state is never affected by
+					// reorganizations => only state that is built upon best blocks is rewound. ACL
+					// cache is keyed by best block and is invalidated when best block is changed
+					if let best_block_tracker::BestBlockChange::Reorg { ref retracted, .. } = best_block_change {
+						key_server_set.retract_blocks(retracted);
+					}
+
+					let best_block = best_block_change.best_block();
+					acl_storage.set_best_block(best_block);
+					key_server_set.set_best_block(best_block).await;
+				},
+				_ = fut_health => (),
+				service_result = fut_service => {
+					error!(
+						target: "secretstore",
+						"Substrate service has stopped: {:?}",
+						service_result,
+					);
+
+					break;
+				},
 			}
 		}
 	});
 }
 
+fn exit_on_keystore_error(result: Result<(), keystore::Error>) {
+	if let Err(error) = result {
+		error!(
+			target: "secretstore",
+			"Keystore command has failed: {}",
+			error,
+		);
+
+		std::process::exit(1);
+	}
+}
+
 fn initialize() {
 	let mut builder = env_logger::Builder::new();
 
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use codec::{Compact, Decode};
+use frame_metadata::{
+	DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType, StorageHasher,
+};
+
+/// Name of the System module.
+const SYSTEM_MODULE: &'static str = "System";
+/// Name of the SecretStore module.
+const SECRET_STORE_MODULE: &'static str = "SecretStore";
+/// Version of runtime metadata that we're able to decode.
+const SUPPORTED_METADATA_VERSION: u8 = 11;
+
+/// All possible errors that can occur when using runtime metadata.
+#[derive(Debug)]
+pub enum Error {
+	/// Failed to decode metadata or runtime events.
+	Decode(codec::Error),
+	/// Metadata version is not supported.
+	UnsupportedVersion(u8),
+	/// Module is not declared in runtime metadata.
+	UnknownModule(String),
+	/// Storage item is not declared in runtime metadata.
+	UnknownStorage(String, String),
+	/// Storage item has unexpected type.
+	UnexpectedStorageType(String, String),
+	/// Module with given events index is not declared in runtime metadata.
+	UnknownEventModule(u8),
+	/// Event with given index is not declared in runtime metadata of given module.
+	UnknownEvent(String, u8),
+	/// Argument of given module event has type that we're unable to decode.
+	UnknownEventArgumentType(String, String, String),
+	/// Event of given module can not be decoded.
+	UndecodableEvent(String, codec::Error),
+}
+
+/// Runtime metadata that is required to derive storage keys and decode events.
+#[derive(Debug)]
+pub struct Metadata {
+	/// Runtime modules.
+	modules: HashMap<String, Module>,
+	/// Modules that have events, ordered by their events index.
+	event_modules: Vec<EventModule>,
+}
+
+/// Runtime module metadata.
+#[derive(Debug)]
+struct Module {
+	/// Storage prefix.
+	storage_prefix: String,
+	/// Storage items by name. Hasher is None for plain storage values.
+	storage: HashMap<String, Option<StorageHasher>>,
+}
+
+/// Runtime module events metadata.
+#[derive(Debug)]
+struct EventModule {
+	/// Module name.
+	name: String,
+	/// Module events, ordered by their index.
+	events: Vec<EventMetadata>,
+}
+
+/// Runtime event metadata.
+#[derive(Debug)]
+struct EventMetadata {
+	/// Event name.
+	name: String,
+	/// Types of event arguments.
+	arguments: Vec<String>,
+}
+
+/// Runtime event record.
+#[derive(Debug)]
+pub struct EventRecord {
+	/// Phase of the block where event has been emitted.
+	pub phase: frame_system::Phase,
+	/// Event itself.
+	pub event: Event,
+}
+
+/// Runtime event.
+#[derive(Debug)]
+pub enum Event {
+	/// System module event.
+	System(crate::runtime::SystemEvent),
+	/// SecretStore module event.
+	SecretStore(substrate_secret_store_runtime::Event),
+	/// Event of other module.
+	Other,
+}
+
+impl Metadata {
+	/// Decode SCALE-encoded metadata, returned by `state_getMetadata` RPC.
+	pub fn decode(encoded: &[u8]) -> Result<Self, Error> {
+		// metadata of older versions can't be decoded at all, so check version before decoding
+		let version = *encoded.get(4).ok_or_else(|| Error::Decode("no metadata version".into()))?;
+		if version != SUPPORTED_METADATA_VERSION {
+			return Err(Error::UnsupportedVersion(version));
+		}
+
+		let metadata = RuntimeMetadataPrefixed::decode(&mut &encoded[..]).map_err(Error::Decode)?;
+		let metadata = match metadata.1 {
+			RuntimeMetadata::V11(metadata) => metadata,
+			_ => return Err(Error::UnsupportedVersion(version)),
+		};
+
+		let mut modules = HashMap::new();
+		let mut event_modules = Vec::new();
+		for module in decoded(metadata.modules)? {
+			let name = decoded(module.name)?;
+			let (storage_prefix, storage) = match module.storage {
+				Some(storage) => {
+					let storage = decoded(storage)?;
+					let entries = decoded(storage.entries)?
+						.into_iter()
+						.map(|entry| Ok((
+							decoded(entry.name)?,
+							match entry.ty {
+								StorageEntryType::Plain(_) => None,
+								StorageEntryType::Map { hasher, .. } => Some(hasher),
+								StorageEntryType::DoubleMap { hasher, .. } => Some(hasher),
+							},
+						)))
+						.collect::<Result<_, Error>>()?;
+					(decoded(storage.prefix)?, entries)
+				},
+				None => (name.clone(), HashMap::new()),
+			};
+			if let Some(events) = module.event {
+				event_modules.push(EventModule {
+					name: name.clone(),
+					events: decoded(events)?
+						.into_iter()
+						.map(|event| Ok(EventMetadata {
+							name: decoded(event.name)?,
+							arguments: decoded(event.arguments)?,
+						}))
+						.collect::<Result<_, Error>>()?,
+				});
+			}
+
+			modules.insert(name, Module {
+				storage_prefix,
+				storage,
+			});
+		}
+
+		Ok(Metadata {
+			modules,
+			event_modules,
+		})
+	}
+
+	/// Get storage key of plain storage value.
+	pub fn storage_value_key(&self, module: &str, item: &str) -> Result<Vec<u8>, Error> {
+		match self.storage_item(module, item)? {
+			(prefix, None) => Ok(storage_prefix(prefix, item)),
+			(_, Some(_)) => Err(Error::UnexpectedStorageType(module.into(), item.into())),
+		}
+	}
+
+	/// Get storage key of storage map entry with given encoded key.
+	pub fn storage_map_key(&self, module: &str, item: &str, key: &[u8]) -> Result<Vec<u8>, Error> {
+		match self.storage_item(module, item)? {
+			(prefix, Some(hasher)) => {
+				let mut storage_key = storage_prefix(prefix, item);
+				storage_key.extend(hash_key(hasher, key));
+				Ok(storage_key)
+			},
+			(_, None) => Err(Error::UnexpectedStorageType(module.into(), item.into())),
+		}
+	}
+
+	/// Get storage key of System::Events.
+	pub fn system_events_key(&self) -> Result<Vec<u8>, Error> {
+		self.storage_value_key(SYSTEM_MODULE, "Events")
+	}
+
+	/// Decode SCALE-encoded System::Events storage value. Modules are identified using
+	/// metadata, so events of System and SecretStore modules are decoded even if module
+	/// indices have changed. Events of other modules are skipped using argument types,
+	/// declared in metadata. If such event can't be skipped, the whole block events can't
+	/// be decoded and error is returned.
+	pub fn decode_events(&self, encoded: &[u8]) -> Result<Vec<EventRecord>, Error> {
+		let input = &mut &encoded[..];
+		let events_count = Compact::<u32>::decode(input).map_err(Error::Decode)?.0;
+		let mut events = Vec::with_capacity(events_count as usize);
+		for _ in 0..events_count {
+			let phase = frame_system::Phase::decode(input).map_err(Error::Decode)?;
+			let module_index = u8::decode(input).map_err(Error::Decode)?;
+			let module = self.event_modules.get(module_index as usize)
+				.ok_or(Error::UnknownEventModule(module_index))?;
+			let undecodable_event = |error| Error::UndecodableEvent(module.name.clone(), error);
+			let event = match module.name.as_str() {
+				SYSTEM_MODULE => Event::System(Decode::decode(input).map_err(undecodable_event)?),
+				SECRET_STORE_MODULE => Event::SecretStore(Decode::decode(input).map_err(undecodable_event)?),
+				_ => {
+					module.skip_event(input)?;
+					Event::Other
+				},
+			};
+			// topics are not used
+			Vec::<crate::runtime::BlockHash>::decode(input).map_err(Error::Decode)?;
+
+			events.push(EventRecord {
+				phase,
+				event,
+			});
+		}
+
+		Ok(events)
+	}
+
+	/// Get storage prefix and hasher of storage item.
+	fn storage_item(&self, module: &str, item: &str) -> Result<(&str, Option<StorageHasher>), Error> {
+		let module_metadata = self.modules.get(module).ok_or_else(|| Error::UnknownModule(module.into()))?;
+		let hasher = module_metadata.storage.get(item)
+			.ok_or_else(|| Error::UnknownStorage(module.into(), item.into()))?;
+		Ok((&module_metadata.storage_prefix, hasher.clone()))
+	}
+}
+
+impl EventModule {
+	/// Skip SCALE-encoded event of this module, using argument types from metadata.
+	fn skip_event(&self, input: &mut &[u8]) -> Result<(), Error> {
+		let event_index = u8::decode(input).map_err(|error| Error::UndecodableEvent(self.name.clone(), error))?;
+		let event = self.events.get(event_index as usize)
+			.ok_or_else(|| Error::UnknownEvent(self.name.clone(), event_index))?;
+		for argument in &event.arguments {
+			crate::event_arguments::skip(argument, input).map_err(|error| match error {
+				crate::event_arguments::Error::UnknownType(_) => Error::UnknownEventArgumentType(
+					self.name.clone(),
+					event.name.clone(),
+					argument.clone(),
+				),
+				crate::event_arguments::Error::Decode(error) => Error::UndecodableEvent(self.name.clone(), error),
+			})?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Get decoded value of metadata field.
+fn decoded<B, O>(value: DecodeDifferent<B, O>) -> Result<O, Error> {
+	match value {
+		DecodeDifferent::Decoded(value) => Ok(value),
+		DecodeDifferent::Encode(_) => Err(Error::Decode("unexpected encode-only metadata field".into())),
+	}
+}
+
+/// Get storage prefix of storage item.
+fn storage_prefix(module_prefix: &str, item: &str) -> Vec<u8> {
+	let mut storage_key = sp_core::hashing::twox_128(module_prefix.as_bytes()).to_vec();
+	storage_key.extend_from_slice(&sp_core::hashing::twox_128(item.as_bytes()));
+	storage_key
+}
+
+/// Hash storage map key using given hasher.
+fn hash_key(hasher: StorageHasher, key: &[u8]) -> Vec<u8> {
+	match hasher {
+		StorageHasher::Blake2_128 => sp_core::hashing::blake2_128(key).to_vec(),
+		StorageHasher::Blake2_256 => sp_core::hashing::blake2_256(key).to_vec(),
+		StorageHasher::Blake2_128Concat => [&sp_core::hashing::blake2_128(key)[..], key].concat(),
+		StorageHasher::Twox128 => sp_core::hashing::twox_128(key).to_vec(),
+		StorageHasher::Twox256 => sp_core::hashing::twox_256(key).to_vec(),
+		StorageHasher::Twox64Concat => [&sp_core::hashing::twox_64(key)[..], key].concat(),
+		StorageHasher::Identity => key.to_vec(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use codec::Encode;
+	use super::*;
+
+	fn metadata() -> Metadata {
+		let module = |storage: Vec<(&str, Option<StorageHasher>)>| Module {
+			storage_prefix: String::new(),
+			storage: storage.into_iter().map(|(name, hasher)| (name.into(), hasher)).collect(),
+		};
+		let mut modules = HashMap::new();
+		modules.insert(SYSTEM_MODULE.into(), Module {
+			storage_prefix: SYSTEM_MODULE.into(),
+			..module(vec![("Events", None), ("Account", Some(StorageHasher::Blake2_128Concat))])
+		});
+		modules.insert("Balances".into(), Module {
+			storage_prefix: "Balances".into(),
+			..module(vec![("TotalIssuance", None)])
+		});
+		modules.insert(SECRET_STORE_MODULE.into(), Module {
+			storage_prefix: SECRET_STORE_MODULE.into(),
+			..module(vec![])
+		});
+
+		let event_module = |name: &str, events: Vec<(&str, Vec<&str>)>| EventModule {
+			name: name.into(),
+			events: events.into_iter().map(|(name, arguments)| EventMetadata {
+				name: name.into(),
+				arguments: arguments.into_iter().map(Into::into).collect(),
+			}).collect(),
+		};
+
+		Metadata {
+			modules,
+			event_modules: vec![
+				event_module(SYSTEM_MODULE, vec![]),
+				event_module("Balances", vec![
+					("Endowed", vec!["AccountId", "Balance"]),
+					("Transfer", vec!["AccountId", "AccountId", "Balance"]),
+				]),
+				event_module(SECRET_STORE_MODULE, vec![]),
+				event_module("Custom", vec![
+					("Unused", vec![]),
+					("Stored", vec!["u32", "Vec<u8>", "BalanceOf<T>"]),
+					("Opaque", vec!["T::Opaque"]),
+				]),
+			],
+		}
+	}
+
+	fn encode_events(events: Vec<Vec<u8>>) -> Vec<u8> {
+		let mut encoded = Compact(events.len() as u32).encode();
+		for (index, event) in events.into_iter().enumerate() {
+			encoded.extend(frame_system::Phase::ApplyExtrinsic(index as u32).encode());
+			encoded.extend(event);
+			encoded.extend(Vec::<crate::runtime::BlockHash>::new().encode());
+		}
+		encoded
+	}
+
+	fn system_event(module_index: u8) -> Vec<u8> {
+		let mut event = vec![module_index];
+		event.extend(crate::runtime::SystemEvent::ExtrinsicSuccess(Default::default()).encode());
+		event
+	}
+
+	#[test]
+	fn system_events_key_is_computed() {
+		assert_eq!(
+			hex::encode(metadata().system_events_key().unwrap()),
+			"26aa394eea5630e07c48ae0c9558cef780d41e5e16056765bc8461851072c9d7",
+		);
+	}
+
+	#[test]
+	fn storage_map_key_is_computed_using_declared_hasher() {
+		let key = [1u8, 2, 3];
+		let storage_key = metadata().storage_map_key(SYSTEM_MODULE, "Account", &key).unwrap();
+
+		assert_eq!(
+			hex::encode(&storage_key[..32]),
+			"26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9",
+		);
+		assert_eq!(&storage_key[32..48], &sp_core::hashing::blake2_128(&key)[..]);
+		assert_eq!(&storage_key[48..], &key[..]);
+	}
+
+	#[test]
+	fn storage_key_of_undeclared_or_mismatched_item_is_not_computed() {
+		let metadata = metadata();
+		match metadata.storage_map_key(SYSTEM_MODULE, "Events", &[]) {
+			Err(Error::UnexpectedStorageType(_, _)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match metadata.storage_value_key(SYSTEM_MODULE, "Account") {
+			Err(Error::UnexpectedStorageType(_, _)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match metadata.storage_value_key(SYSTEM_MODULE, "Unknown") {
+			Err(Error::UnknownStorage(_, _)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match metadata.storage_value_key("Unknown", "Events") {
+			Err(Error::UnknownModule(_)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn system_events_are_decoded() {
+		let events = metadata().decode_events(&encode_events(vec![system_event(0), system_event(0)])).unwrap();
+
+		assert_eq!(events.len(), 2);
+		for (index, event) in events.into_iter().enumerate() {
+			match event.phase {
+				frame_system::Phase::ApplyExtrinsic(phase_index) => assert_eq!(phase_index, index as u32),
+				phase => panic!("unexpected phase: {:?}", phase),
+			}
+			match event.event {
+				Event::System(frame_system::RawEvent::ExtrinsicSuccess(_)) => (),
+				event => panic!("unexpected event: {:?}", event),
+			}
+		}
+	}
+
+	#[test]
+	fn empty_events_are_decoded() {
+		assert!(metadata().decode_events(&encode_events(vec![])).unwrap().is_empty());
+	}
+
+	#[test]
+	fn events_of_foreign_modules_are_skipped_using_metadata() {
+		// event layout is unknown to the runtime that we're built with
+		let mut custom_event = vec![3, 1];
+		custom_event.extend((42u32, vec![1u8, 2, 3], 100u128).encode());
+		let mut transfer_event = vec![1, 1];
+		transfer_event.extend(([1u8; 32], [2u8; 32], 100u128).encode());
+
+		let events = metadata().decode_events(&encode_events(vec![
+			custom_event,
+			system_event(0),
+			transfer_event,
+			system_event(0),
+		])).unwrap();
+
+		assert_eq!(events.len(), 4);
+		match (&events[0].event, &events[1].event, &events[2].event, &events[3].event) {
+			(Event::Other, Event::System(_), Event::Other, Event::System(_)) => (),
+			events => panic!("unexpected events: {:?}", events),
+		}
+	}
+
+	#[test]
+	fn events_of_unknown_module_are_not_decoded() {
+		match metadata().decode_events(&encode_events(vec![system_event(4)])) {
+			Err(Error::UnknownEventModule(4)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn unknown_events_of_foreign_modules_are_not_decoded() {
+		match metadata().decode_events(&encode_events(vec![vec![3, 3]])) {
+			Err(Error::UnknownEvent(ref module, 3)) if module == "Custom" => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match metadata().decode_events(&encode_events(vec![vec![3, 2, 0]])) {
+			Err(Error::UnknownEventArgumentType(ref module, ref event, ref ty))
+				if module == "Custom" && event == "Opaque" && ty == "T::Opaque" => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn metadata_of_unsupported_version_is_not_decoded() {
+		let mut encoded = frame_metadata::META_RESERVED.encode();
+		encoded.push(10);
+		match Metadata::decode(&encoded) {
+			Err(Error::UnsupportedVersion(10)) => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn undecodable_events_are_not_skipped() {
+		match metadata().decode_events(&encode_events(vec![vec![1, 0, 1]])) {
+			Err(Error::UndecodableEvent(ref module, _)) if module == "Balances" => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match metadata().decode_events(&encode_events(vec![vec![0, 0xFF]])) {
+			Err(Error::UndecodableEvent(ref module, _)) if module == SYSTEM_MODULE => (),
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn truncated_events_are_not_decoded() {
+		let mut encoded = encode_events(vec![system_event(0)]);
+		encoded.pop();
+
+		assert!(metadata().decode_events(&encoded).is_err());
+	}
+}
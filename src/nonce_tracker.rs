@@ -0,0 +1,149 @@
+use log::warn;
+use crate::runtime::Index;
+
+/// Number of consecutive checks that must observe the same gap before we decide
+/// that transactions have been dropped from the pool.
+const GAP_CHECKS_BEFORE_RESYNC: u32 = 3;
+
+/// In-process tracker of signer account indices (nonces).
+///
+/// Indices are allocated sequentially, so concurrent submissions never share the
+/// same index. Tracker is resynced from the chain when the node rejects transaction
+/// because of its index, or when transactions are silently dropped from the pool.
+#[derive(Debug, Default)]
+pub struct NonceTracker {
+	/// Next index to allocate. None if it needs to be read from the chain.
+	next_index: Option<Index>,
+	/// Number of consecutive checks that have observed a gap.
+	gap_checks: u32,
+}
+
+impl NonceTracker {
+	/// Returns true if tracker needs to be synced with the chain before allocating index.
+	pub fn is_sync_required(&self) -> bool {
+		self.next_index.is_none()
+	}
+
+	/// Set next index, read from the chain.
+	pub fn sync(&mut self, chain_next_index: Index) {
+		self.next_index = Some(chain_next_index);
+		self.gap_checks = 0;
+	}
+
+	/// Forget about allocated indices. Next allocation will require sync.
+	pub fn invalidate(&mut self) {
+		self.next_index = None;
+		self.gap_checks = 0;
+	}
+
+	/// Allocate next index. Returns None if sync is required.
+	pub fn allocate(&mut self) -> Option<Index> {
+		let index = self.next_index?;
+		self.next_index = Some(index + 1);
+		Some(index)
+	}
+
+	/// Compare local index with the chain index (that accounts ready transactions from the pool).
+	/// If chain index has been lagging for several consecutive checks, then some of our
+	/// transactions have been dropped => we need to fill the gap by reusing their indices.
+	pub fn check_gap(&mut self, chain_next_index: Index) {
+		match self.next_index {
+			Some(next_index) if chain_next_index < next_index => {
+				self.gap_checks += 1;
+				if self.gap_checks >= GAP_CHECKS_BEFORE_RESYNC {
+					warn!(
+						target: "secretstore",
+						"Transactions with indices {}..{} have been dropped. Reusing their indices",
+						chain_next_index,
+						next_index,
+					);
+
+					self.sync(chain_next_index);
+				}
+			},
+			Some(next_index) if chain_next_index > next_index => {
+				// someone else is using the same account
+				self.sync(chain_next_index);
+			},
+			_ => self.gap_checks = 0,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn synced_tracker(next_index: Index) -> NonceTracker {
+		let mut tracker = NonceTracker::default();
+		tracker.sync(next_index);
+		tracker
+	}
+
+	#[test]
+	fn sync_is_required_before_first_allocation() {
+		let mut tracker = NonceTracker::default();
+		assert!(tracker.is_sync_required());
+		assert_eq!(tracker.allocate(), None);
+
+		tracker.sync(10);
+		assert!(!tracker.is_sync_required());
+		assert_eq!(tracker.allocate(), Some(10));
+	}
+
+	#[test]
+	fn indices_are_allocated_sequentially() {
+		let mut tracker = synced_tracker(5);
+		assert_eq!(tracker.allocate(), Some(5));
+		assert_eq!(tracker.allocate(), Some(6));
+		assert_eq!(tracker.allocate(), Some(7));
+	}
+
+	#[test]
+	fn invalidate_requires_resync() {
+		let mut tracker = synced_tracker(5);
+		tracker.allocate();
+		tracker.invalidate();
+
+		assert!(tracker.is_sync_required());
+		assert_eq!(tracker.allocate(), None);
+
+		tracker.sync(6);
+		assert_eq!(tracker.allocate(), Some(6));
+	}
+
+	#[test]
+	fn gap_is_filled_after_several_consecutive_checks() {
+		let mut tracker = synced_tracker(5);
+		tracker.allocate();
+		tracker.allocate();
+
+		for _ in 0..GAP_CHECKS_BEFORE_RESYNC - 1 {
+			tracker.check_gap(5);
+			assert_eq!(tracker.next_index, Some(7));
+		}
+		tracker.check_gap(5);
+		assert_eq!(tracker.allocate(), Some(5));
+	}
+
+	#[test]
+	fn gap_checks_are_reset_when_gap_is_closed() {
+		let mut tracker = synced_tracker(5);
+		tracker.allocate();
+
+		for _ in 0..GAP_CHECKS_BEFORE_RESYNC - 1 {
+			tracker.check_gap(5);
+		}
+		tracker.check_gap(6);
+		tracker.check_gap(5);
+
+		assert_eq!(tracker.allocate(), Some(6));
+	}
+
+	#[test]
+	fn tracker_is_resynced_when_chain_is_ahead() {
+		let mut tracker = synced_tracker(5);
+		tracker.check_gap(8);
+		assert_eq!(tracker.allocate(), Some(8));
+	}
+}
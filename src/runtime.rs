@@ -1,12 +1,161 @@
-pub type BlockHash = node_primitives::Hash;
-pub type TransactionHash = node_primitives::Hash;
-pub type Header = node_runtime::Header;
-pub type Event = node_runtime::Event;
-pub type Call = node_runtime::Call;
-pub type Runtime = node_runtime::Runtime;
-pub type SignedPayload = node_runtime::SignedPayload;
-pub type UncheckedExtrinsic = node_runtime::UncheckedExtrinsic;
-
-pub type AccountId = node_primitives::AccountId;
-pub type Balance = node_primitives::Balance;
-pub type Index = node_primitives::Index;
\ No newline at end of file
+use codec::{Decode, Encode};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::signer::Signer;
+
+/// Runtime that is used by the key server. Runtime is selected by cargo feature. To run
+/// key server against other chain with SecretStore pallet, implement `SecretStoreRuntime`
+/// for its runtime and select it under new feature.
+#[cfg(feature = "node")]
+pub type DefaultRuntime = node::NodeRuntime;
+
+#[cfg(not(feature = "node"))]
+compile_error!("Runtime is not selected. Enable one of runtime features (e.g. `node`)");
+
+pub type BlockHash = <DefaultRuntime as SecretStoreRuntime>::BlockHash;
+pub type BlockNumber = <DefaultRuntime as SecretStoreRuntime>::BlockNumber;
+pub type TransactionHash = <DefaultRuntime as SecretStoreRuntime>::BlockHash;
+pub type Header = <DefaultRuntime as SecretStoreRuntime>::Header;
+pub type Call = <DefaultRuntime as SecretStoreRuntime>::Call;
+pub type Runtime = <DefaultRuntime as SecretStoreRuntime>::Runtime;
+pub type UncheckedExtrinsic = <DefaultRuntime as SecretStoreRuntime>::Extrinsic;
+pub type SecretStoreCall = substrate_secret_store_runtime::Call<Runtime>;
+pub type SystemEvent = frame_system::Event<Runtime>;
+
+pub type AccountId = sp_runtime::AccountId32;
+pub type Balance = <DefaultRuntime as SecretStoreRuntime>::Balance;
+pub type Index = <DefaultRuntime as SecretStoreRuntime>::Index;
+pub type AccountData = <DefaultRuntime as SecretStoreRuntime>::AccountData;
+pub type AccountInfo = frame_system::AccountInfo<Index, AccountData>;
+
+/// Runtime-specific types and transaction format of the chain with SecretStore pallet.
+///
+/// Transactions are signed by `Signer` that produces `MultiSignature` and is identified by
+/// `AccountId32` account. So only runtimes that use `AccountId32` accounts and accept
+/// `MultiSignature` signatures are supported.
+pub trait SecretStoreRuntime {
+	/// FRAME runtime that embeds System and SecretStore pallets.
+	type Runtime: substrate_secret_store_runtime::Trait + frame_system::Trait<
+		Hash = Self::BlockHash,
+		BlockNumber = Self::BlockNumber,
+		Header = Self::Header,
+		AccountId = AccountId,
+		Index = Self::Index,
+		AccountData = Self::AccountData,
+	>;
+	/// Block hash type.
+	type BlockHash: Copy + Eq + std::hash::Hash + std::fmt::Debug + std::fmt::Display + std::fmt::LowerHex
+		+ Encode + Decode + Serialize + DeserializeOwned + Send + Sync + 'static;
+	/// Block number type.
+	type BlockNumber: Copy + Ord + std::fmt::Debug + std::fmt::Display
+		+ Encode + Decode + Serialize + DeserializeOwned + Send + Sync + 'static;
+	/// Block header type.
+	type Header: sp_runtime::traits::Header<Number = Self::BlockNumber, Hash = Self::BlockHash>
+		+ DeserializeOwned + Send + Sync + 'static;
+	/// Runtime call type.
+	type Call: Clone + Encode + Send + Sync + 'static;
+	/// Signed extrinsic type.
+	type Extrinsic: Encode;
+	/// Balance type.
+	type Balance: Copy + Ord + Default + std::fmt::Debug + std::fmt::Display
+		+ Encode + Decode + Serialize + DeserializeOwned + Send + Sync + 'static;
+	/// Account index (nonce) type.
+	type Index: Copy + Ord + std::fmt::Debug + std::fmt::Display
+		+ Encode + Decode + Serialize + DeserializeOwned + Send + Sync + 'static;
+	/// Account data, stored in `System::Account`.
+	type AccountData: Decode + Default;
+
+	/// Get free balance from the account data.
+	fn free_balance(account_data: &Self::AccountData) -> Self::Balance;
+
+	/// Wrap SecretStore pallet call into runtime call.
+	fn secret_store_call(call: substrate_secret_store_runtime::Call<Self::Runtime>) -> Self::Call;
+
+	/// Create extrinsic, signed with `MultiSignature` of the signer, with runtime-specific
+	/// signed extensions.
+	fn create_transaction(
+		call: Self::Call,
+		signer: &Signer,
+		index: Self::Index,
+		genesis_hash: Self::BlockHash,
+		runtime_version: u32,
+		era: sp_runtime::generic::Era,
+		era_hash: Self::BlockHash,
+		tip: Self::Balance,
+	) -> Self::Extrinsic;
+}
+
+#[cfg(feature = "node")]
+mod node {
+	use codec::Encode;
+	use sp_runtime::traits::IdentifyAccount;
+	use crate::signer::Signer;
+	use super::SecretStoreRuntime;
+
+	/// Substrate node runtime (`node_runtime`).
+	pub struct NodeRuntime;
+
+	impl SecretStoreRuntime for NodeRuntime {
+		type Runtime = node_runtime::Runtime;
+		type BlockHash = node_primitives::Hash;
+		type BlockNumber = node_primitives::BlockNumber;
+		type Header = node_runtime::Header;
+		type Call = node_runtime::Call;
+		type Extrinsic = node_runtime::UncheckedExtrinsic;
+		type Balance = node_primitives::Balance;
+		type Index = node_primitives::Index;
+		type AccountData = pallet_balances::AccountData<Self::Balance>;
+
+		fn free_balance(account_data: &Self::AccountData) -> Self::Balance {
+			account_data.free
+		}
+
+		fn secret_store_call(call: substrate_secret_store_runtime::Call<Self::Runtime>) -> Self::Call {
+			node_runtime::Call::SecretStore(call)
+		}
+
+		fn create_transaction(
+			call: Self::Call,
+			signer: &Signer,
+			index: Self::Index,
+			genesis_hash: Self::BlockHash,
+			runtime_version: u32,
+			era: sp_runtime::generic::Era,
+			era_hash: Self::BlockHash,
+			tip: Self::Balance,
+		) -> Self::Extrinsic {
+			let extra = |i: Self::Index, f: Self::Balance| {
+				(
+					frame_system::CheckVersion::<Self::Runtime>::new(),
+					frame_system::CheckGenesis::<Self::Runtime>::new(),
+					frame_system::CheckEra::<Self::Runtime>::from(era),
+					frame_system::CheckNonce::<Self::Runtime>::from(i),
+					frame_system::CheckWeight::<Self::Runtime>::new(),
+					pallet_transaction_payment::ChargeTransactionPayment::<Self::Runtime>::from(f),
+					Default::default(),
+				)
+			};
+			let raw_payload = node_runtime::SignedPayload::from_raw(
+				call,
+				extra(index, tip),
+				(
+					runtime_version,
+					genesis_hash,
+					era_hash,
+					(),
+					(),
+					(),
+					(),
+				),
+			);
+			let signature = raw_payload.using_encoded(|payload| signer.sign(payload));
+			let (function, extra, _) = raw_payload.deconstruct();
+
+			node_runtime::UncheckedExtrinsic::new_signed(
+				function,
+				signer.public().into_account().into(),
+				signature,
+				extra,
+			)
+		}
+	}
+}
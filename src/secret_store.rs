@@ -1,30 +1,32 @@
 use std::sync::Arc;
-use parity_crypto::publickey::KeyPair;
+use log::{info, warn};
 use parity_secretstore_primitives::{
 	error::Error,
 	executor::TokioHandle,
-	key_server_key_pair::{KeyServerKeyPair, InMemoryKeyServerKeyPair},
-	key_storage::InMemoryKeyStorage,
+	key_server_key_pair::KeyServerKeyPair,
+	key_storage::{KeyStorage, InMemoryKeyStorage},
 };
 use parity_secretstore_key_server::{ClusterConfiguration, KeyServerImpl};
 use crate::{
 	acl_storage::OnChainAclStorage,
+	configuration::{KeyServerConfiguration, KeyStorageConfiguration},
 	key_server_set::OnChainKeyServerSet,
-	substrate_client::Client,
+	key_storage::{PersistentKeyStorage, RocksDbDatabase},
+	key_storage_encryption::EncryptedDatabase,
 };
 
 /// Start Secret Store key server.
 pub fn start(
 	executor: TokioHandle,
-	key_pair: KeyPair,
-	listen_port: u16,
+	key_server_key_pair: Arc<dyn KeyServerKeyPair>,
+	config: KeyServerConfiguration,
+	key_storage_config: KeyStorageConfiguration,
 	acl_storage: Arc<OnChainAclStorage>,
 	key_server_set: Arc<OnChainKeyServerSet>,
 ) -> Result<Arc<KeyServerImpl>, Error> {
-	let key_server_key_pair = Arc::new(InMemoryKeyServerKeyPair::new(key_pair));
-	let key_storage = Arc::new(InMemoryKeyStorage::default());
+	let key_storage = create_key_storage(key_storage_config)?;
 	let key_server_config = ClusterConfiguration {
-		admin_address: None,
+		admin_address: config.admin_address,
 		auto_migrate_enabled: true,
 	};
 	parity_secretstore_key_server::Builder::new()
@@ -35,9 +37,53 @@ pub fn start(
 		.build_for_tcp(
 			executor,
 			parity_secretstore_key_server::network::tcp::NodeAddress {
-				address: "127.0.0.1".into(),
-				port: listen_port,
+				address: config.listen_address,
+				port: config.listen_port,
 			},
 			key_server_set,
 		)
 }
+
+/// Create key storage.
+fn create_key_storage(config: KeyStorageConfiguration) -> Result<Arc<dyn KeyStorage>, Error> {
+	match config.path {
+		Some(path) => {
+			info!(
+				target: "secretstore",
+				"Using key storage database at {}",
+				path.display(),
+			);
+
+			let db = RocksDbDatabase::open(&path)?;
+			let import_file = config.import_file.as_ref().map(|path| path.as_path());
+			match config.sealing_key {
+				Some(sealing_key) => {
+					let db = EncryptedDatabase::open(db, sealing_key, config.previous_sealing_keys)?;
+					Ok(Arc::new(PersistentKeyStorage::open(db, import_file)?))
+				},
+				None => {
+					if crate::key_storage_encryption::is_sealed(&db)? {
+						return Err(Error::Internal(
+							"Key storage database is sealed, but sealing key is not specified".into(),
+						));
+					}
+
+					warn!(
+						target: "secretstore",
+						"Key storage sealing key is not specified. Key shares will be stored unencrypted",
+					);
+
+					Ok(Arc::new(PersistentKeyStorage::open(db, import_file)?))
+				},
+			}
+		},
+		None => {
+			warn!(
+				target: "secretstore",
+				"Key storage database path is not specified. All key shares will be lost on restart",
+			);
+
+			Ok(Arc::new(InMemoryKeyStorage::default()))
+		},
+	}
+}
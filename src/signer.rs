@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use sp_core::crypto::Pair;
+use sp_runtime::{
+	MultiSignature, MultiSigner,
+	traits::IdentifyAccount,
+};
+
+/// All possible errors that can occur when loading signer.
+#[derive(Debug)]
+pub enum Error {
+	/// Failed to read file.
+	Io(PathBuf, std::io::Error),
+	/// Keystore file has invalid format.
+	InvalidKeystoreFile(PathBuf, serde_json::Error),
+	/// Failed to parse secret.
+	InvalidSecret(sp_core::crypto::SecretStringError),
+}
+
+/// Signature scheme of the signer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureScheme {
+	/// Schnorrkel/Ristretto x25519.
+	Sr25519,
+	/// Ed25519.
+	Ed25519,
+	/// ECDSA/SECP256k1.
+	Ecdsa,
+}
+
+/// Source of the signer secret.
+#[derive(Debug)]
+pub enum SignerSource {
+	/// Secret URI (`//Alice`, hex-encoded seed or mnemonic phrase with optional derivation path).
+	Uri(String),
+	/// File that contains BIP39 mnemonic phrase.
+	MnemonicFile(PathBuf),
+	/// Substrate keystore file.
+	KeystoreFile(PathBuf),
+}
+
+/// Transactions signer. Transactions are signed with `MultiSignature`.
+#[derive(Clone)]
+pub enum Signer {
+	/// Sr25519 key pair.
+	Sr25519(sp_core::sr25519::Pair),
+	/// Ed25519 key pair.
+	Ed25519(sp_core::ed25519::Pair),
+	/// ECDSA key pair.
+	Ecdsa(sp_core::ecdsa::Pair),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match *self {
+			Error::Io(ref path, ref error) => write!(f, "failed to read {}: {}", path.display(), error),
+			Error::InvalidKeystoreFile(ref path, ref error) => write!(f, "invalid keystore file {}: {}", path.display(), error),
+			Error::InvalidSecret(ref error) => write!(f, "invalid secret: {:?}", error),
+		}
+	}
+}
+
+impl std::str::FromStr for SignatureScheme {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"sr25519" => Ok(SignatureScheme::Sr25519),
+			"ed25519" => Ok(SignatureScheme::Ed25519),
+			"ecdsa" => Ok(SignatureScheme::Ecdsa),
+			_ => Err(format!("unknown signature scheme {}. Expected one of: sr25519, ed25519, ecdsa", s)),
+		}
+	}
+}
+
+impl Signer {
+	/// Load signer from given source.
+	pub fn load(
+		scheme: SignatureScheme,
+		source: SignerSource,
+		password_file: Option<&Path>,
+	) -> Result<Self, Error> {
+		let secret = match source {
+			SignerSource::Uri(uri) => uri,
+			SignerSource::MnemonicFile(path) => read_file(&path)?.trim().to_owned(),
+			SignerSource::KeystoreFile(path) => {
+				// Substrate keystore file contains JSON-encoded secret phrase or seed
+				let contents = read_file(&path)?;
+				serde_json::from_str::<String>(&contents)
+					.map_err(|error| Error::InvalidKeystoreFile(path, error))?
+			},
+		};
+		let password = password_file
+			.map(|path| read_file(path).map(|password| password.trim_end_matches(&['\r', '\n'][..]).to_owned()))
+			.transpose()?;
+		let password = password.as_ref().map(|password| password.as_str());
+
+		match scheme {
+			SignatureScheme::Sr25519 => sp_core::sr25519::Pair::from_string(&secret, password)
+				.map(Signer::Sr25519),
+			SignatureScheme::Ed25519 => sp_core::ed25519::Pair::from_string(&secret, password)
+				.map(Signer::Ed25519),
+			SignatureScheme::Ecdsa => sp_core::ecdsa::Pair::from_string(&secret, password)
+				.map(Signer::Ecdsa),
+		}.map_err(Error::InvalidSecret)
+	}
+
+	/// Get public key of the signer.
+	pub fn public(&self) -> MultiSigner {
+		match *self {
+			Signer::Sr25519(ref pair) => pair.public().into(),
+			Signer::Ed25519(ref pair) => pair.public().into(),
+			Signer::Ecdsa(ref pair) => pair.public().into(),
+		}
+	}
+
+	/// Get account id of the signer. Account id is derived from `MultiSigner`, so it is
+	/// only valid for runtimes with `AccountId32` accounts.
+	pub fn account_id(&self) -> crate::runtime::AccountId {
+		self.public().into_account()
+	}
+
+	/// Sign the message.
+	pub fn sign(&self, message: &[u8]) -> MultiSignature {
+		match *self {
+			Signer::Sr25519(ref pair) => pair.sign(message).into(),
+			Signer::Ed25519(ref pair) => pair.sign(message).into(),
+			Signer::Ecdsa(ref pair) => pair.sign(message).into(),
+		}
+	}
+}
+
+/// Read file contents.
+fn read_file(path: &Path) -> Result<String, Error> {
+	std::fs::read_to_string(path).map_err(|error| Error::Io(path.into(), error))
+}
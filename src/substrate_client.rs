@@ -1,5 +1,3 @@
-// TODO: track account indices
-
 // Copyright 2020 Parity Technologies (UK) Ltd.
 // This file is part of Parity-Bridge.
 
@@ -18,13 +16,55 @@
 
 // https://github.com/scs/substrate-api-client/blob/master/src/examples/example_event_callback.rs
 
+use std::{
+	collections::{HashMap, VecDeque},
+	ops::Range,
+	sync::Arc,
+	time::Duration,
+};
 use codec::{Decode, Encode};
-use sp_core::crypto::Pair;
-use sp_runtime::traits::IdentifyAccount;
+use futures::{future::{BoxFuture, Either, FutureExt}, Stream};
+use log::{debug, error, info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, de::DeserializeOwned};
+use sp_core::Get;
+use sp_runtime::traits::Header as HeaderT;
+use crate::{
+	fee_policy::FeePolicy,
+	metadata::{EventRecord, Metadata},
+	nonce_tracker::NonceTracker,
+	signer::Signer,
+};
 
-/// System::events storage key. Calculated as:
-/// twox_128(b"System").to_vec() ++ twox_128(b"Events").to_vec()
-const SYSTEM_EVENTS_KEY: &'static str = "26aa394eea5630e07c48ae0c9558cef780d41e5e16056765bc8461851072c9d7";
+/// If we have not received subscription notification for this period, we check that
+/// connection is still alive.
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Interval between health checks of the active connection.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Max number of missing finalized headers that are read at once.
+const MAX_BACKFILL_HEADERS: crate::runtime::BlockNumber = 64;
+/// Initial delay before reconnecting to the node.
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+/// Max delay before reconnecting to the node.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+/// Max number of times request is retried after transport error.
+const MAX_REQUEST_RETRIES: u32 = 4;
+/// Max number of times expired transaction is re-signed and resubmitted.
+const MAX_EXPIRED_TRANSACTION_RESUBMISSIONS: u32 = 3;
+/// If we have not received transaction status update for this period, we stop watching it.
+const TRANSACTION_STATUS_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Code of the author RPC error, returned when transaction is invalid. Error data
+/// holds the reason (i.e. `"Stale"` if transaction index is outdated).
+const POOL_INVALID_TX_ERROR: i64 = 1010;
+/// Code of the author RPC error, returned when transaction with the same index is
+/// already in the pool and has higher priority.
+const POOL_TOO_LOW_PRIORITY_ERROR: i64 = 1014;
+/// Runtime APIs that are used by the key server, with supported versions.
+const REQUIRED_RUNTIME_APIS: &'static [(&'static str, u32)] = &[
+	("SecretStoreAclApi", 1),
+	("SecretStoreServiceApi", 1),
+	("SecretStoreKeyServerSetApi", 1),
+];
 
 /// All possible errors that can occur during interacting with Substrate node.
 #[derive(Debug)]
@@ -35,72 +75,526 @@ pub enum Error {
 	RequestFailed(jsonrpsee::client::RequestError),
 	/// Response decode has failed.
 	DecodeFailed(codec::Error),
+	/// Node has returned unexpected genesis hash.
+	UnexpectedGenesisHash(crate::runtime::BlockHash),
+	/// Requested block is unknown to the node.
+	UnknownBlock(crate::runtime::BlockNumber),
+	/// Node is not aware of the block with given hash.
+	UnknownBlockHash(crate::runtime::BlockHash),
+	/// Node is major syncing.
+	NodeIsSyncing,
+	/// No endpoints are configured.
+	NoEndpoints,
+	/// Transaction is not found in the block where it has been included.
+	TransactionNotFound(crate::runtime::TransactionHash, crate::runtime::BlockHash),
+	/// Signer balance is below configured threshold.
+	InsufficientBalance(crate::runtime::Balance),
+	/// Transaction era period is longer than number of block hashes kept by the runtime.
+	TransactionEraTooLong(u64, u64),
+	/// Runtime metadata is invalid or doesn't match the runtime.
+	Metadata(crate::metadata::Error),
+	/// Request future has been dropped before completion.
+	Canceled,
+}
+
+impl From<crate::async_bridge::Canceled> for Error {
+	fn from(_: crate::async_bridge::Canceled) -> Self {
+		Error::Canceled
+	}
+}
+
+/// Final outcome of the watched transaction.
+#[derive(Debug)]
+pub enum TransactionOutcome {
+	/// Transaction has been included into finalized block and dispatched.
+	Finalized {
+		/// Hash of the transaction.
+		transaction_hash: crate::runtime::TransactionHash,
+		/// Hash of the finalized block where transaction has been included.
+		block_hash: crate::runtime::BlockHash,
+		/// Transaction dispatch result.
+		dispatch_result: Result<(), sp_runtime::DispatchError>,
+	},
+	/// Transaction has been included into block, but it has not been finalized in time.
+	FinalityTimeout(crate::runtime::BlockHash),
+	/// Transaction has been replaced by other transaction with the same index.
+	Usurped(crate::runtime::TransactionHash),
+	/// Transaction has been dropped from the pool.
+	Dropped,
+	/// Transaction has been declared invalid by the pool.
+	Invalid,
+	/// Transaction status is unknown, because node has stopped sending updates.
+	Unknown,
+}
+
+/// Status of the transaction, as reported by `author_submitAndWatchExtrinsic`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum TransactionStatus {
+	/// Transaction is in the future queue.
+	Future,
+	/// Transaction is in the ready queue.
+	Ready,
+	/// Transaction has been broadcast to given peers.
+	Broadcast(Vec<String>),
+	/// Transaction has been included into block.
+	InBlock(crate::runtime::BlockHash),
+	/// Block with the transaction has been retracted.
+	Retracted(crate::runtime::BlockHash),
+	/// Block with the transaction has not been finalized in time.
+	FinalityTimeout(crate::runtime::BlockHash),
+	/// Block with the transaction has been finalized.
+	Finalized(crate::runtime::BlockHash),
+	/// Transaction has been replaced by other transaction.
+	Usurped(crate::runtime::TransactionHash),
+	/// Transaction has been dropped from the pool.
+	Dropped,
+	/// Transaction is invalid.
+	Invalid,
+}
+
+/// Signed transaction.
+struct SignedTransaction {
+	/// Hash of the transaction.
+	hash: crate::runtime::TransactionHash,
+	/// Encoded transaction.
+	encoded: Vec<u8>,
+	/// Number of the first block where mortal transaction is invalid. None if transaction is immortal.
+	death: Option<u64>,
+}
+
+/// Response of `payment_queryInfo` RPC.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeInfo {
+	/// Estimated transaction fee (without tip).
+	partial_fee: crate::runtime::Balance,
+}
+
+/// Response of `chain_getBlock` RPC.
+#[derive(Deserialize)]
+struct SignedBlock {
+	/// Block itself.
+	block: Block,
+}
+
+/// Block with opaque extrinsics.
+#[derive(Deserialize)]
+struct Block {
+	/// Encoded block extrinsics.
+	extrinsics: Vec<sp_core::Bytes>,
 }
 
 /// Substrate client type.
 #[derive(Clone)]
 pub struct Client {
+	/// Substrate nodes URIs.
+	endpoints: Arc<Vec<String>>,
+	/// Transactions signer.
+	signer: Signer,
+	/// Active connection to the Substrate node.
+	connection: Arc<RwLock<Connection>>,
+	/// Runtime metadata, by runtime spec version.
+	metadata_cache: Arc<RwLock<HashMap<u32, Arc<Metadata>>>>,
+	/// Signer account indices tracker.
+	nonce_tracker: Arc<futures::lock::Mutex<NonceTracker>>,
+	/// Number of blocks while signed transactions are valid. Transactions are immortal if None.
+	transaction_era: Option<u64>,
+	/// Transactions fee policy.
+	fee_policy: FeePolicy,
+}
+
+/// Connection to the Substrate node.
+#[derive(Clone)]
+struct Connection {
+	/// Index of connected endpoint.
+	endpoint_index: usize,
+	/// Connection generation, incremented on every failover.
+	generation: u64,
 	/// Substrate RPC client.
 	rpc_client: jsonrpsee::Client,
-	/// Transactions signer.
-	signer: sp_core::sr25519::Pair,
 	/// Genesis block hash.
 	genesis_hash: crate::runtime::BlockHash,
 	/// Runtime version.
 	runtime_version: u32,
+	/// Runtime metadata.
+	metadata: Arc<Metadata>,
+}
+
+/// Response of `system_health` RPC.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Health {
+	/// Is node syncing?
+	is_syncing: bool,
+}
+
+/// State of finalized headers stream.
+struct FinalizedHeaders {
+	/// Substrate client.
+	client: Client,
+	/// Active finalized headers subscription.
+	subscription: Option<jsonrpsee::client::Subscription<crate::runtime::Header>>,
+	/// Generation of connection that has been used to open subscription.
+	subscription_generation: u64,
+	/// True if we need to fail over to other endpoint before subscribing.
+	failover_required: bool,
+	/// Number of the last header that has been yielded by the stream.
+	best_finalized_number: Option<crate::runtime::BlockNumber>,
+	/// Headers that are ready to be yielded.
+	queue: VecDeque<crate::runtime::Header>,
+	/// Header that is yielded after all missing headers before it are read.
+	backfill_header: Option<crate::runtime::Header>,
 }
 
 impl Client {
-	/// Create new client.
+	/// Create new client, connected to the first healthy endpoint.
 	pub async fn new(
-		uri: &str,
-		signer: sp_core::sr25519::Pair,
+		endpoints: Vec<String>,
+		signer: Signer,
+		transaction_era: Option<u64>,
+		fee_policy: FeePolicy,
 	) -> Result<Self, Error> {
-		let rpc_client = jsonrpsee::ws_client(uri).await.map_err(Error::ClientCreationFailed)?;
-		let genesis_hash = rpc_client.request(
-			"chain_getBlockHash",
+		if let Some(period) = transaction_era {
+			check_transaction_era(period)?;
+		}
+
+		let mut last_error = Error::NoEndpoints;
+		for (endpoint_index, endpoint) in endpoints.iter().enumerate() {
+			match Connection::open(endpoint, endpoint_index, None, None).await {
+				Ok(connection) => return Ok(Client {
+					endpoints: Arc::new(endpoints),
+					signer,
+					metadata_cache: Arc::new(RwLock::new(
+						std::iter::once((connection.runtime_version, connection.metadata.clone())).collect(),
+					)),
+					connection: Arc::new(RwLock::new(connection)),
+					nonce_tracker: Arc::new(futures::lock::Mutex::new(NonceTracker::default())),
+					transaction_era,
+					fee_policy,
+				}),
+				Err(error) => {
+					warn!(
+						target: "secretstore",
+						"Failed to connect to {}: {:?}",
+						endpoint,
+						error,
+					);
+
+					last_error = error;
+				},
+			}
+		}
+
+		Err(last_error)
+	}
+
+	/// Switch to the next healthy endpoint (that knows given block, if specified). Failed
+	/// endpoint is tried last, so it is reconnected if other endpoints are not healthy.
+	/// Does nothing if connection has already been switched since given generation.
+	pub async fn failover(&self, failed_generation: u64, at: Option<crate::runtime::BlockHash>) -> Result<(), Error> {
+		let (first_endpoint_index, genesis_hash) = {
+			let connection = self.connection.read();
+			if connection.generation != failed_generation {
+				return Ok(());
+			}
+
+			(connection.endpoint_index + 1, connection.genesis_hash)
+		};
+
+		let mut last_error = Error::NoEndpoints;
+		for offset in 0..self.endpoints.len() {
+			let endpoint_index = (first_endpoint_index + offset) % self.endpoints.len();
+			let endpoint = &self.endpoints[endpoint_index];
+			match Connection::open(endpoint, endpoint_index, Some(genesis_hash), at).await {
+				Ok(mut connection) => {
+					let mut current_connection = self.connection.write();
+					if current_connection.generation == failed_generation {
+						info!(
+							target: "secretstore",
+							"Switched to Substrate node {}",
+							endpoint,
+						);
+
+						connection.generation = failed_generation + 1;
+						*current_connection = connection;
+					}
+
+					return Ok(());
+				},
+				Err(error) => {
+					warn!(
+						target: "secretstore",
+						"Substrate node {} is not healthy: {:?}",
+						endpoint,
+						error,
+					);
+
+					last_error = error;
+				},
+			}
+		}
+
+		Err(last_error)
+	}
+
+	/// Periodically check health of the active connection and switch to other endpoint
+	/// if connection is dead or node is syncing. Never completes.
+	pub async fn monitor_health(self) {
+		loop {
+			futures_timer::Delay::new(HEALTH_CHECK_INTERVAL).await;
+
+			let generation = self.connection_generation();
+			let error = match self.rpc_client().request::<Health>(
+				"system_health",
+				jsonrpsee::core::common::Params::None,
+			).await {
+				Ok(ref health) if !health.is_syncing => continue,
+				Ok(_) => Error::NodeIsSyncing,
+				Err(error) => Error::RequestFailed(error),
+			};
+
+			warn!(
+				target: "secretstore",
+				"Substrate node is not healthy: {:?}. Switching to other Substrate node",
+				error,
+			);
+
+			if let Err(error) = self.failover(generation, None).await {
+				warn!(
+					target: "secretstore",
+					"Failed to switch to other Substrate node: {:?}",
+					error,
+				);
+			}
+		}
+	}
+
+	/// Refresh cached runtime version using runtime version at given block.
+	pub async fn refresh_runtime_version(&self, at: crate::runtime::BlockHash) -> Result<(), Error> {
+		let runtime_version: sp_version::RuntimeVersion = self.request(
+			"state_getRuntimeVersion",
 			jsonrpsee::core::common::Params::Array(vec![
-				serde_json::to_value(0u32).unwrap(),
+				serde_json::to_value(at).unwrap(),
 			]),
-		).await.map_err(Error::RequestFailed)?;
-		let runtime_version: sp_version::RuntimeVersion = rpc_client.request(
-			"state_getRuntimeVersion",
-			jsonrpsee::core::common::Params::None,
-		).await.map_err(Error::RequestFailed)?;
+			Some(at),
+		).await?;
 
-		Ok(Client {
-			rpc_client,
-			signer,
-			genesis_hash,
-			runtime_version: runtime_version.spec_version,
-		})
+		let previous_spec_version = self.connection.read().runtime_version;
+		if previous_spec_version != runtime_version.spec_version {
+			let metadata = self.runtime_metadata(at, runtime_version.spec_version).await?;
+
+			{
+				let mut connection = self.connection.write();
+				connection.runtime_version = runtime_version.spec_version;
+				connection.metadata = metadata;
+			}
+
+			info!(
+				target: "secretstore",
+				"Runtime has been upgraded at block {}: spec_version {} -> {}",
+				at,
+				previous_spec_version,
+				runtime_version.spec_version,
+			);
+
+			check_runtime_apis(&runtime_version);
+		}
+
+		Ok(())
+	}
+
+	/// Returns stream of finalized headers. Connection is switched to other endpoint if
+	/// it is dead. Headers that have been finalized while we were disconnected are read
+	/// from the node, so every finalized header is yielded exactly once, in order.
+	/// If last processed block is specified, stream starts from its child.
+	pub fn finalized_headers(
+		&self,
+		last_processed_number: Option<crate::runtime::BlockNumber>,
+	) -> impl Stream<Item = crate::runtime::Header> {
+		futures::stream::unfold(
+			FinalizedHeaders {
+				client: self.clone(),
+				subscription: None,
+				subscription_generation: 0,
+				failover_required: false,
+				best_finalized_number: last_processed_number,
+				queue: VecDeque::new(),
+				backfill_header: None,
+			},
+			|mut state| async move {
+				let header = state.next().await;
+				Some((header, state))
+			},
+		)
 	}
 
 	/// Subscribe to new blocks.
 	pub async fn subscribe_finalized_heads(&self) -> Result<jsonrpsee::client::Subscription<crate::runtime::Header>, Error> {
-		self.rpc_client.subscribe(
+		self.rpc_client().subscribe(
 			"chain_subscribeFinalizedHeads",
 			jsonrpsee::core::common::Params::None,
 			"chain_unsubscribeFinalizedHeads",
 		).await.map_err(Error::RequestFailed)
 	}
 
-	/// Read events of the header.
-	pub async fn header_events(&self, hash: crate::runtime::BlockHash) -> Result<Vec<frame_system::EventRecord<crate::runtime::Event, crate::runtime::BlockHash>>, Error> {
-		let events_storage: Option<sp_core::Bytes> = self.rpc_client.request(
+	/// Subscribe to new best blocks.
+	pub async fn subscribe_new_heads(&self) -> Result<jsonrpsee::client::Subscription<crate::runtime::Header>, Error> {
+		self.rpc_client().subscribe(
+			"chain_subscribeNewHeads",
+			jsonrpsee::core::common::Params::None,
+			"chain_unsubscribeNewHeads",
+		).await.map_err(Error::RequestFailed)
+	}
+
+	/// Returns stream of new best (not necessarily finalized) headers. Connection is switched
+	/// to other endpoint if it is dead. Headers that have been imported while we were
+	/// disconnected are not yielded, so consumers should read missing ancestors themselves.
+	pub fn new_headers(&self) -> impl Stream<Item = crate::runtime::Header> {
+		futures::stream::unfold(
+			(self.clone(), None),
+			|(client, mut subscription)| async move {
+				loop {
+					let (current_subscription, generation) = match subscription {
+						Some(ref mut subscription) => subscription,
+						None => {
+							subscription = Some(client.subscribe_new_heads_with_backoff().await);
+							continue;
+						},
+					};
+
+					let generation = *generation;
+					let header = match futures::future::select(
+						Box::pin(current_subscription.next()),
+						futures_timer::Delay::new(CONNECTION_CHECK_INTERVAL),
+					).await {
+						Either::Left((header, _)) => Some(header),
+						Either::Right(_) => None,
+					};
+
+					match header {
+						Some(header) => return Some((header, (client, subscription))),
+						None => {
+							let error = match client.check_connection().await {
+								Ok(()) => continue,
+								Err(error) => error,
+							};
+
+							warn!(
+								target: "secretstore",
+								"Connection to Substrate node is dead: {:?}. Switching to other Substrate node",
+								error,
+							);
+
+							subscription = None;
+							if let Err(error) = client.failover(generation, None).await {
+								warn!(
+									target: "secretstore",
+									"Failed to switch to other Substrate node: {:?}",
+									error,
+								);
+							}
+						},
+					}
+				}
+			},
+		)
+	}
+
+	/// Get hash of the best finalized block.
+	pub async fn finalized_head(&self) -> Result<crate::runtime::BlockHash, Error> {
+		self.request(
+			"chain_getFinalizedHead",
+			jsonrpsee::core::common::Params::None,
+			None,
+		).await
+	}
+
+	/// Get hash of the block with given number.
+	pub async fn block_hash(&self, number: crate::runtime::BlockNumber) -> Result<Option<crate::runtime::BlockHash>, Error> {
+		self.request(
+			"chain_getBlockHash",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(number).unwrap(),
+			]),
+			None,
+		).await
+	}
+
+	/// Get header of the block with given hash.
+	pub async fn header(&self, hash: crate::runtime::BlockHash) -> Result<Option<crate::runtime::Header>, Error> {
+		self.request(
+			"chain_getHeader",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(hash).unwrap(),
+			]),
+			Some(hash),
+		).await
+	}
+
+	/// Read headers of blocks with given numbers. Headers are requested concurrently,
+	/// so range should be reasonably small.
+	pub async fn headers(&self, range: Range<crate::runtime::BlockNumber>) -> Result<Vec<crate::runtime::Header>, Error> {
+		futures::future::try_join_all(range.map(|number| async move {
+			let hash = self.block_hash(number).await?.ok_or(Error::UnknownBlock(number))?;
+			self.header(hash).await?.ok_or(Error::UnknownBlock(number))
+		})).await
+	}
+
+	/// Read events of the header. Events are decoded using metadata of the runtime at this header.
+	pub async fn header_events(&self, hash: crate::runtime::BlockHash) -> Result<Vec<EventRecord>, Error> {
+		let runtime_version: sp_version::RuntimeVersion = self.request(
+			"state_getRuntimeVersion",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(hash).unwrap(),
+			]),
+			Some(hash),
+		).await?;
+		let metadata = self.runtime_metadata(hash, runtime_version.spec_version).await?;
+		let events_key = metadata.system_events_key().map_err(Error::Metadata)?;
+		let events_storage: Option<sp_core::Bytes> = self.request(
 			"state_getStorage",
 			jsonrpsee::core::common::Params::Array(vec![
-				serde_json::to_value(format!("0x{}", SYSTEM_EVENTS_KEY)).unwrap(),
+				serde_json::to_value(sp_core::Bytes(events_key)).unwrap(),
 				serde_json::to_value(hash).unwrap(),
 			]),
-		).await.map_err(Error::RequestFailed)?;
+			Some(hash),
+		).await?;
 		match events_storage {
-			Some(events_storage) => Decode::decode(&mut &events_storage[..])
-				.map_err(Error::DecodeFailed),
+			Some(events_storage) => metadata.decode_events(&events_storage[..])
+				.map_err(Error::Metadata),
 			None => Ok(Vec::new())
 		}
 	}
 
+	/// Get metadata of the current runtime.
+	fn metadata(&self) -> Arc<Metadata> {
+		self.connection.read().metadata.clone()
+	}
+
+	/// Get metadata of the runtime with given spec version, reading it at given block if
+	/// it is not cached yet.
+	async fn runtime_metadata(
+		&self,
+		at: crate::runtime::BlockHash,
+		spec_version: u32,
+	) -> Result<Arc<Metadata>, Error> {
+		if let Some(metadata) = self.metadata_cache.read().get(&spec_version) {
+			return Ok(metadata.clone());
+		}
+
+		let metadata: sp_core::Bytes = self.request(
+			"state_getMetadata",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(at).unwrap(),
+			]),
+			Some(at),
+		).await?;
+		let metadata = Arc::new(Metadata::decode(&metadata.0).map_err(Error::Metadata)?);
+		self.metadata_cache.write().insert(spec_version, metadata.clone());
+		Ok(metadata)
+	}
+
 	/// Call runtime method.
 	pub async fn call_runtime_method<Ret: Decode>(
 		&self,
@@ -108,91 +602,736 @@ impl Client {
 		method: &'static str,
 		arguments: Vec<Vec<u8>>,
 	) -> Result<Ret, Error> {
-		self.rpc_client.request(
+		self.request(
 			"state_call",
 			jsonrpsee::core::common::Params::Array(vec![
 				serde_json::to_value(method).unwrap(),
-				serde_json::to_value(arguments.encode()).unwrap(),
+				serde_json::to_value(sp_core::Bytes(arguments.concat())).unwrap(),
 				serde_json::to_value(hash).unwrap(),
 			]),
+			Some(hash),
 		)
 		.await
-		.map_err(Error::RequestFailed)
 		.and_then(|ret: sp_core::Bytes| Ret::decode(&mut &ret.0[..]).map_err(Error::DecodeFailed))
 	}
 
-	/// Submit runtime transaction.
-	pub async fn submit_transaction(&self, call: crate::runtime::Call) -> Result<crate::runtime::BlockHash, Error> {
-		let index = self.next_account_index().await?;
-		let transaction = create_transaction(
-			call,
+	/// Submit runtime transaction. Returns hash of the submitted transaction and future
+	/// that watches it until it is finalized or rejected. Mortal transaction that has
+	/// expired before inclusion is re-signed and resubmitted by this future.
+	pub async fn submit_transaction(
+		&self,
+		call: crate::runtime::Call,
+	) -> Result<(crate::runtime::TransactionHash, BoxFuture<'static, Result<TransactionOutcome, Error>>), Error> {
+		self.ensure_sufficient_balance().await?;
+
+		let (transaction, subscription) = self.retry_on_index_error(
+			|attempt| self.submit_and_watch_transaction_with_allocated_index(call.clone(), attempt)
+		).await?;
+		let transaction_hash = transaction.hash;
+		let client = self.clone();
+		Ok((
+			transaction_hash,
+			async move { client.watch_and_resubmit_transaction(call, transaction, subscription).await }.boxed(),
+		))
+	}
+
+	/// Submit runtime transaction and watch it until it is finalized or rejected.
+	pub async fn submit_and_watch_transaction(&self, call: crate::runtime::Call) -> Result<TransactionOutcome, Error> {
+		let (_, outcome) = self.submit_transaction(call).await?;
+		outcome.await
+	}
+
+	/// Watch submitted transaction until it is finalized or rejected, re-signing and
+	/// resubmitting it if it expires before inclusion.
+	async fn watch_and_resubmit_transaction(
+		&self,
+		call: crate::runtime::Call,
+		mut transaction: SignedTransaction,
+		mut subscription: jsonrpsee::client::Subscription<TransactionStatus>,
+	) -> Result<TransactionOutcome, Error> {
+		let mut resubmissions = 0;
+		loop {
+			let outcome = self.watch_transaction(transaction.hash, subscription).await?;
+			match outcome {
+				TransactionOutcome::Dropped | TransactionOutcome::Invalid | TransactionOutcome::Unknown
+					if resubmissions < MAX_EXPIRED_TRANSACTION_RESUBMISSIONS
+						&& self.is_transaction_expired(&transaction).await? =>
+				{
+					warn!(
+						target: "secretstore",
+						"Transaction {} has expired before inclusion. Re-signing",
+						transaction.hash,
+					);
+
+					// index of expired transaction is not used => it should be reused
+					self.nonce_tracker.lock().await.invalidate();
+					resubmissions += 1;
+
+					let (new_transaction, new_subscription) = self.retry_on_index_error(
+						|attempt| self.submit_and_watch_transaction_with_allocated_index(call.clone(), resubmissions + attempt)
+					).await?;
+					transaction = new_transaction;
+					subscription = new_subscription;
+				},
+				outcome => return Ok(outcome),
+			}
+		}
+	}
+
+	/// Check if some of our transactions have been dropped from the pool and reuse their indices.
+	pub async fn check_account_index_gap(&self) -> Result<(), Error> {
+		if self.nonce_tracker.lock().await.is_sync_required() {
+			return Ok(());
+		}
+
+		let chain_next_index = self.next_account_index().await?;
+		self.nonce_tracker.lock().await.check_gap(chain_next_index);
+		Ok(())
+	}
+
+	/// Sign transaction with the next allocated index, submit it and subscribe to its status updates.
+	async fn submit_and_watch_transaction_with_allocated_index(
+		&self,
+		call: crate::runtime::Call,
+		attempt: u32,
+	) -> Result<(SignedTransaction, jsonrpsee::client::Subscription<TransactionStatus>), Error> {
+		let transaction = self.sign_transaction(call, attempt).await?;
+		let subscription = self.rpc_client().subscribe(
+			"author_submitAndWatchExtrinsic",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(sp_core::Bytes(transaction.encoded.clone())).unwrap(),
+			]),
+			"author_unwatchExtrinsic",
+		).await.map_err(Error::RequestFailed)?;
+		Ok((transaction, subscription))
+	}
+
+	/// Sign transaction with the next allocated index. Mortal transactions are anchored
+	/// to the latest finalized block. Tip is computed using fee policy and (0-based)
+	/// submission attempt.
+	async fn sign_transaction(&self, call: crate::runtime::Call, attempt: u32) -> Result<SignedTransaction, Error> {
+		let (era, era_hash) = match self.transaction_era {
+			Some(period) => {
+				let finalized_hash = self.finalized_head().await?;
+				let finalized_number = *self.header(finalized_hash).await?
+					.ok_or(Error::UnknownBlockHash(finalized_hash))?
+					.number();
+				// era is quantized, so its checkpoint is the block where era has started
+				let era = sp_runtime::generic::Era::mortal(period, finalized_number as u64);
+				let birth_number = era.birth(finalized_number as u64) as crate::runtime::BlockNumber;
+				let birth_hash = if birth_number == finalized_number {
+					finalized_hash
+				} else {
+					self.block_hash(birth_number).await?.ok_or(Error::UnknownBlock(birth_number))?
+				};
+				(era, Some((birth_number, birth_hash)))
+			},
+			None => (sp_runtime::generic::Era::Immortal, None),
+		};
+
+		let index = self.allocate_account_index().await?;
+		let (genesis_hash, runtime_version) = {
+			let connection = self.connection.read();
+			(connection.genesis_hash, connection.runtime_version)
+		};
+		let sign = |tip| <crate::runtime::DefaultRuntime as crate::runtime::SecretStoreRuntime>::create_transaction(
+			call.clone(),
 			&self.signer,
 			index,
-			self.genesis_hash,
-			self.runtime_version,
-		);
-		self.rpc_client.request(
-			"author_submitExtrinsic",
+			genesis_hash,
+			runtime_version,
+			era,
+			era_hash.map(|(_, hash)| hash).unwrap_or(genesis_hash),
+			tip,
+		).encode();
+
+		let estimated_fee = if self.fee_policy.requires_fee_estimation() {
+			self.estimate_fee(sign(0)).await?
+		} else {
+			0
+		};
+		let tip = self.fee_policy.tip(estimated_fee, attempt);
+		if tip != 0 {
+			debug!(
+				target: "secretstore",
+				"Signing transaction with index {}, estimated fee {} and tip {}",
+				index,
+				estimated_fee,
+				tip,
+			);
+		}
+
+		let encoded = sign(tip);
+
+		Ok(SignedTransaction {
+			hash: sp_core::hashing::blake2_256(&encoded).into(),
+			encoded,
+			death: era_hash.map(|(number, _)| era.death(number as u64)),
+		})
+	}
+
+	/// Estimate fee of the encoded transaction.
+	async fn estimate_fee(&self, transaction: Vec<u8>) -> Result<crate::runtime::Balance, Error> {
+		let fee_info: FeeInfo = self.request(
+			"payment_queryInfo",
 			jsonrpsee::core::common::Params::Array(vec![
-				serde_json::to_value(transaction.encode()).unwrap(),
+				serde_json::to_value(sp_core::Bytes(transaction)).unwrap(),
 			]),
-		).await.map_err(Error::RequestFailed)
+			None,
+		).await?;
+		Ok(fee_info.partial_fee)
+	}
+
+	/// Refuse to submit transactions if signer balance is below configured threshold.
+	async fn ensure_sufficient_balance(&self) -> Result<(), Error> {
+		if self.fee_policy.min_balance == 0 {
+			return Ok(());
+		}
+
+		let account_id = self.signer.account_id();
+		let storage_key = self.metadata()
+			.storage_map_key("System", "Account", &account_id.encode())
+			.map_err(Error::Metadata)?;
+		let account_info: Option<sp_core::Bytes> = self.request(
+			"state_getStorage",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(sp_core::Bytes(storage_key)).unwrap(),
+			]),
+			None,
+		).await?;
+		let balance = match account_info {
+			Some(account_info) => <crate::runtime::DefaultRuntime as crate::runtime::SecretStoreRuntime>::free_balance(
+				&crate::runtime::AccountInfo::decode(&mut &account_info.0[..])
+					.map_err(Error::DecodeFailed)?
+					.data
+			),
+			None => Default::default(),
+		};
+
+		if balance < self.fee_policy.min_balance {
+			return Err(Error::InsufficientBalance(balance));
+		}
+
+		Ok(())
+	}
+
+	/// Returns true if transaction can not be included into any block after the latest finalized block.
+	async fn is_transaction_expired(&self, transaction: &SignedTransaction) -> Result<bool, Error> {
+		let death = match transaction.death {
+			Some(death) => death,
+			None => return Ok(false),
+		};
+
+		let finalized_hash = self.finalized_head().await?;
+		let finalized_number = *self.header(finalized_hash).await?
+			.ok_or(Error::UnknownBlockHash(finalized_hash))?
+			.number();
+		Ok(finalized_number as u64 + 1 >= death)
+	}
+
+	/// Watch transaction status updates until transaction is finalized or rejected.
+	async fn watch_transaction(
+		&self,
+		transaction_hash: crate::runtime::TransactionHash,
+		mut subscription: jsonrpsee::client::Subscription<TransactionStatus>,
+	) -> Result<TransactionOutcome, Error> {
+		loop {
+			let status = match futures::future::select(
+				Box::pin(subscription.next()),
+				futures_timer::Delay::new(TRANSACTION_STATUS_TIMEOUT),
+			).await {
+				Either::Left((status, _)) => status,
+				Either::Right(_) => {
+					warn!(
+						target: "secretstore",
+						"No status updates received for transaction {} for {}s",
+						transaction_hash,
+						TRANSACTION_STATUS_TIMEOUT.as_secs(),
+					);
+
+					return Ok(TransactionOutcome::Unknown);
+				},
+			};
+
+			match status {
+				TransactionStatus::Future | TransactionStatus::Ready | TransactionStatus::Broadcast(_) => debug!(
+					target: "secretstore",
+					"Transaction {} status: {:?}",
+					transaction_hash,
+					status,
+				),
+				TransactionStatus::InBlock(block_hash) => info!(
+					target: "secretstore",
+					"Transaction {} has been included into block {}",
+					transaction_hash,
+					block_hash,
+				),
+				TransactionStatus::Retracted(block_hash) => warn!(
+					target: "secretstore",
+					"Block {} with transaction {} has been retracted",
+					block_hash,
+					transaction_hash,
+				),
+				TransactionStatus::FinalityTimeout(block_hash) =>
+					return Ok(TransactionOutcome::FinalityTimeout(block_hash)),
+				TransactionStatus::Finalized(block_hash) => {
+					let dispatch_result = self.transaction_dispatch_result(transaction_hash, block_hash).await?;
+					return Ok(TransactionOutcome::Finalized {
+						transaction_hash,
+						block_hash,
+						dispatch_result,
+					});
+				},
+				TransactionStatus::Usurped(other_transaction_hash) =>
+					return Ok(TransactionOutcome::Usurped(other_transaction_hash)),
+				TransactionStatus::Dropped => return Ok(TransactionOutcome::Dropped),
+				TransactionStatus::Invalid => return Ok(TransactionOutcome::Invalid),
+			}
+		}
+	}
+
+	/// Read dispatch result of the transaction from the events of the block where it has been included.
+	async fn transaction_dispatch_result(
+		&self,
+		transaction_hash: crate::runtime::TransactionHash,
+		block_hash: crate::runtime::BlockHash,
+	) -> Result<Result<(), sp_runtime::DispatchError>, Error> {
+		let block: Option<SignedBlock> = self.request(
+			"chain_getBlock",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(block_hash).unwrap(),
+			]),
+			Some(block_hash),
+		).await?;
+		let transaction_index = block
+			.and_then(|block| block.block.extrinsics.iter().position(|extrinsic|
+				crate::runtime::TransactionHash::from(sp_core::hashing::blake2_256(&extrinsic.0)) == transaction_hash
+			))
+			.ok_or(Error::TransactionNotFound(transaction_hash, block_hash))?;
+
+		self.header_events(block_hash).await?
+			.into_iter()
+			.filter(|event| event.phase == frame_system::Phase::ApplyExtrinsic(transaction_index as u32))
+			.filter_map(|event| match event.event {
+				crate::metadata::Event::System(frame_system::Event::ExtrinsicSuccess(..)) => Some(Ok(())),
+				crate::metadata::Event::System(frame_system::Event::ExtrinsicFailed(error, ..)) => Some(Err(error)),
+				_ => None,
+			})
+			.next()
+			.ok_or(Error::TransactionNotFound(transaction_hash, block_hash))
+	}
+
+	/// Submit transaction using given function. If transaction is rejected because of
+	/// its index, resync index with the chain and retry. Function receives 0-based
+	/// submission attempt.
+	async fn retry_on_index_error<T, F, Fut>(&self, submit: F) -> Result<T, Error>
+		where
+			F: Fn(u32) -> Fut,
+			Fut: std::future::Future<Output = Result<T, Error>>,
+	{
+		match submit(0).await {
+			Err(ref error) if is_index_error(error) => {
+				warn!(
+					target: "secretstore",
+					"Transaction has been rejected because of its index: {:?}. Retrying with index from the chain",
+					error,
+				);
+
+				self.nonce_tracker.lock().await.invalidate();
+				let result = submit(1).await;
+				if let Err(ref error) = result {
+					if is_index_error(error) {
+						self.nonce_tracker.lock().await.invalidate();
+					}
+				}
+				result
+			},
+			// index of transaction that has failed for other reasons stays allocated, because
+			// concurrent submissions may already use next indices. If it hasn't reached the pool,
+			// the gap is detected and filled by `check_account_index_gap`
+			result => result,
+		}
+	}
+
+	/// Allocate next account index, syncing with the chain if required.
+	async fn allocate_account_index(&self) -> Result<crate::runtime::Index, Error> {
+		let mut nonce_tracker = self.nonce_tracker.lock().await;
+		if nonce_tracker.is_sync_required() {
+			nonce_tracker.sync(self.next_account_index().await?);
+		}
+
+		Ok(nonce_tracker.allocate().expect("tracker has been synced above; qed"))
 	}
 
 	/// Get substrate account nonce.
 	async fn next_account_index(&self) -> Result<crate::runtime::Index, Error> {
 		use sp_core::crypto::Ss58Codec;
 
-		let account_id: crate::runtime::AccountId = self.signer.public().as_array_ref().clone().into();
-		self.rpc_client.request(
+		let account_id = self.signer.account_id();
+		self.request(
 			"system_accountNextIndex",
 			jsonrpsee::core::common::Params::Array(vec![
 				serde_json::to_value(account_id.to_ss58check()).unwrap(),
 			]),
-		).await.map_err(Error::RequestFailed)
+			None,
+		).await
 	}
 }
 
-/// Encode runtime transaction.
-fn create_transaction(
-	call: crate::runtime::Call,
-	signer: &sp_core::sr25519::Pair,
-	index: crate::runtime::Index,
-	genesis_hash: crate::runtime::BlockHash,
-	runtime_version: u32,
-) -> crate::runtime::UncheckedExtrinsic {
-	let extra = |i: crate::runtime::Index, f: crate::runtime::Balance| {
-		(
-			frame_system::CheckVersion::<crate::runtime::Runtime>::new(),
-			frame_system::CheckGenesis::<crate::runtime::Runtime>::new(),
-			frame_system::CheckEra::<crate::runtime::Runtime>::from(sp_runtime::generic::Era::Immortal),
-			frame_system::CheckNonce::<crate::runtime::Runtime>::from(i),
-			frame_system::CheckWeight::<crate::runtime::Runtime>::new(),
-			pallet_transaction_payment::ChargeTransactionPayment::<crate::runtime::Runtime>::from(f),
-			Default::default(),
-		)
-	};
-	let raw_payload = crate::runtime::SignedPayload::from_raw(
-		call,
-		extra(index, 0),
-		(
-			runtime_version,
-			genesis_hash,
+impl Client {
+	/// Subscribe to new best blocks, retrying with backoff. Returns subscription and
+	/// generation of connection that has been used to open it.
+	async fn subscribe_new_heads_with_backoff(
+		&self,
+	) -> (jsonrpsee::client::Subscription<crate::runtime::Header>, u64) {
+		let mut backoff = MIN_RECONNECT_BACKOFF;
+		loop {
+			let generation = self.connection_generation();
+			match self.subscribe_new_heads().await {
+				Ok(subscription) => return (subscription, generation),
+				Err(error) => {
+					warn!(
+						target: "secretstore",
+						"Failed to subscribe to new headers: {:?}. Retrying in {}s",
+						error,
+						backoff.as_secs(),
+					);
+
+					let _ = self.failover(generation, None).await;
+					futures_timer::Delay::new(backoff).await;
+					backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+				},
+			}
+		}
+	}
+
+	/// Get RPC client of the active connection.
+	fn rpc_client(&self) -> jsonrpsee::Client {
+		self.connection.read().rpc_client.clone()
+	}
+
+	/// Check that active connection is alive by sending request over it.
+	async fn check_connection(&self) -> Result<(), Error> {
+		self.rpc_client().request::<Health>(
+			"system_health",
+			jsonrpsee::core::common::Params::None,
+		).await.map(|_| ()).map_err(Error::RequestFailed)
+	}
+
+	/// Get generation of the active connection.
+	pub fn connection_generation(&self) -> u64 {
+		self.connection.read().generation
+	}
+
+	/// Send request to the active endpoint. If request fails because of transport error,
+	/// reconnect to the next healthy endpoint (that knows given block, if specified) and
+	/// retry. The same endpoint is reconnected if there are no other healthy endpoints.
+	/// Retries after the first one are made with backoff. Errors, returned by the node
+	/// itself, are returned to the caller.
+	async fn request<Ret: DeserializeOwned>(
+		&self,
+		method: &'static str,
+		params: jsonrpsee::core::common::Params,
+		at: Option<crate::runtime::BlockHash>,
+	) -> Result<Ret, Error> {
+		let mut backoff = MIN_RECONNECT_BACKOFF;
+		let mut retry = 0;
+		loop {
+			let (rpc_client, generation) = {
+				let connection = self.connection.read();
+				(connection.rpc_client.clone(), connection.generation)
+			};
+			let error = match rpc_client.request(method, params.clone()).await {
+				Ok(ret) => return Ok(ret),
+				Err(error) if retry < MAX_REQUEST_RETRIES && is_transport_error(&error) => error,
+				Err(error) => return Err(Error::RequestFailed(error)),
+			};
+
+			retry += 1;
+			warn!(
+				target: "secretstore",
+				"Request {} has failed: {:?}. Reconnecting to Substrate node ({}/{})",
+				method,
+				error,
+				retry,
+				MAX_REQUEST_RETRIES,
+			);
+
+			if retry > 1 {
+				futures_timer::Delay::new(backoff).await;
+				backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+			}
+
+			if let Err(error) = self.failover(generation, at).await {
+				warn!(
+					target: "secretstore",
+					"Failed to reconnect to Substrate node: {:?}",
+					error,
+				);
+			}
+		}
+	}
+}
+
+impl Connection {
+	/// Open new connection to the node and check that it is healthy.
+	async fn open(
+		endpoint: &str,
+		endpoint_index: usize,
+		expected_genesis_hash: Option<crate::runtime::BlockHash>,
+		at: Option<crate::runtime::BlockHash>,
+	) -> Result<Self, Error> {
+		let rpc_client = jsonrpsee::ws_client(endpoint).await.map_err(Error::ClientCreationFailed)?;
+		let genesis_hash = rpc_client.request(
+			"chain_getBlockHash",
+			jsonrpsee::core::common::Params::Array(vec![
+				serde_json::to_value(0u32).unwrap(),
+			]),
+		).await.map_err(Error::RequestFailed)?;
+		if let Some(expected_genesis_hash) = expected_genesis_hash {
+			if genesis_hash != expected_genesis_hash {
+				return Err(Error::UnexpectedGenesisHash(genesis_hash));
+			}
+		}
+
+		let health: Health = rpc_client.request(
+			"system_health",
+			jsonrpsee::core::common::Params::None,
+		).await.map_err(Error::RequestFailed)?;
+		if health.is_syncing {
+			return Err(Error::NodeIsSyncing);
+		}
+
+		if let Some(at) = at {
+			let header: Option<crate::runtime::Header> = rpc_client.request(
+				"chain_getHeader",
+				jsonrpsee::core::common::Params::Array(vec![
+					serde_json::to_value(at).unwrap(),
+				]),
+			).await.map_err(Error::RequestFailed)?;
+			if header.is_none() {
+				return Err(Error::UnknownBlockHash(at));
+			}
+		}
+
+		let runtime_version: sp_version::RuntimeVersion = rpc_client.request(
+			"state_getRuntimeVersion",
+			jsonrpsee::core::common::Params::None,
+		).await.map_err(Error::RequestFailed)?;
+		check_runtime_apis(&runtime_version);
+
+		let metadata: sp_core::Bytes = rpc_client.request(
+			"state_getMetadata",
+			jsonrpsee::core::common::Params::None,
+		).await.map_err(Error::RequestFailed)?;
+		let metadata = Metadata::decode(&metadata.0).map_err(Error::Metadata)?;
+
+		Ok(Connection {
+			endpoint_index,
+			generation: 0,
+			rpc_client,
 			genesis_hash,
-			(),
-			(),
-			(),
-			(),
-		),
-	);
-	let signature = raw_payload.using_encoded(|payload| signer.sign(payload));
-	let signer: sp_runtime::MultiSigner = signer.public().into();
-	let (function, extra, _) = raw_payload.deconstruct();
-
-	crate::runtime::UncheckedExtrinsic::new_signed(
-		function,
-		signer.into_account().into(),
-		signature.into(),
-		extra,
-	)
+			runtime_version: runtime_version.spec_version,
+			metadata: Arc::new(metadata),
+		})
+	}
+}
+
+impl FinalizedHeaders {
+	/// Read next finalized header.
+	async fn next(&mut self) -> crate::runtime::Header {
+		loop {
+			if let Some(header) = self.queue.pop_front() {
+				self.best_finalized_number = Some(*header.number());
+				return header;
+			}
+
+			if self.backfill_header.is_some() {
+				self.backfill().await;
+				continue;
+			}
+
+			let subscription = match self.subscription {
+				Some(ref mut subscription) => subscription,
+				None => {
+					self.subscription = Some(self.subscribe().await);
+					continue;
+				},
+			};
+
+			let header = match futures::future::select(
+				Box::pin(subscription.next()),
+				futures_timer::Delay::new(CONNECTION_CHECK_INTERVAL),
+			).await {
+				Either::Left((header, _)) => Some(header),
+				Either::Right(_) => None,
+			};
+
+			let header = match header {
+				Some(header) => header,
+				None => {
+					// finality may legitimately stall, so we only switch to other node
+					// if connection itself is dead
+					if let Err(error) = self.client.check_connection().await {
+						warn!(
+							target: "secretstore",
+							"Connection to Substrate node is dead: {:?}. Switching to other Substrate node",
+							error,
+						);
+
+						self.subscription = None;
+						self.failover_required = true;
+					}
+
+					continue;
+				},
+			};
+
+			let header_number = *header.number();
+			match self.best_finalized_number {
+				// duplicate header (we may receive it after resubscribing)
+				Some(best_finalized_number) if header_number <= best_finalized_number => continue,
+				// some headers have been finalized while we were disconnected
+				Some(best_finalized_number) if header_number > best_finalized_number + 1 => {
+					info!(
+						target: "secretstore",
+						"Reading {} missing finalized headers",
+						header_number - best_finalized_number - 1,
+					);
+
+					self.backfill_header = Some(header);
+				},
+				_ => self.queue.push_back(header),
+			}
+		}
+	}
+
+	/// Read next page of missing headers. Backfill header is queued after all missing
+	/// headers are read.
+	async fn backfill(&mut self) {
+		let backfill_number = match self.backfill_header {
+			Some(ref header) => *header.number(),
+			None => return,
+		};
+		let first_missing_number = self.best_finalized_number.map(|number| number + 1).unwrap_or(backfill_number);
+		if first_missing_number >= backfill_number {
+			self.queue.extend(self.backfill_header.take());
+			return;
+		}
+
+		let missing_headers = first_missing_number
+			..std::cmp::min(first_missing_number + MAX_BACKFILL_HEADERS, backfill_number);
+		match self.client.headers(missing_headers).await {
+			Ok(missing_headers) => self.queue.extend(missing_headers),
+			Err(error) => {
+				warn!(
+					target: "secretstore",
+					"Failed to read missing finalized headers: {:?}. Retrying in {}s",
+					error,
+					MIN_RECONNECT_BACKOFF.as_secs(),
+				);
+
+				futures_timer::Delay::new(MIN_RECONNECT_BACKOFF).await;
+			},
+		}
+	}
+
+	/// Subscribe to finalized headers (switching to other endpoint first, if required),
+	/// retrying with backoff.
+	async fn subscribe(&mut self) -> jsonrpsee::client::Subscription<crate::runtime::Header> {
+		let mut backoff = MIN_RECONNECT_BACKOFF;
+		loop {
+			let failover_result = if self.failover_required {
+				self.client.failover(self.subscription_generation, None).await
+			} else {
+				Ok(())
+			};
+			let generation = self.client.connection_generation();
+			let subscribe_result = match failover_result {
+				Ok(()) => self.client.subscribe_finalized_heads().await,
+				Err(error) => Err(error),
+			};
+
+			match subscribe_result {
+				Ok(subscription) => {
+					self.subscription_generation = generation;
+					self.failover_required = false;
+					return subscription;
+				},
+				Err(error) => {
+					warn!(
+						target: "secretstore",
+						"Failed to subscribe to finalized headers: {:?}. Retrying in {}s",
+						error,
+						backoff.as_secs(),
+					);
+
+					self.subscription_generation = generation;
+					self.failover_required = true;
+					futures_timer::Delay::new(backoff).await;
+					backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+				},
+			}
+		}
+	}
+}
+
+/// Check that mortal transactions with given era period could be verified by the runtime.
+fn check_transaction_era(period: u64) -> Result<(), Error> {
+	// period is rounded up to the power of two when era is constructed
+	let quantized_period = period.checked_next_power_of_two().unwrap_or(period).max(4).min(1 << 16);
+	let block_hash_count = <crate::runtime::Runtime as frame_system::Trait>::BlockHashCount::get() as u64;
+	if quantized_period > block_hash_count {
+		return Err(Error::TransactionEraTooLong(quantized_period, block_hash_count));
+	}
+
+	Ok(())
+}
+
+/// Returns true if request has failed because of transport (connection) error.
+fn is_transport_error(error: &jsonrpsee::client::RequestError) -> bool {
+	match *error {
+		jsonrpsee::client::RequestError::TransportError(_) => true,
+		_ => false,
+	}
+}
+
+/// Returns true if transaction has been rejected because of its index.
+fn is_index_error(error: &Error) -> bool {
+	match *error {
+		Error::RequestFailed(jsonrpsee::client::RequestError::Request(ref error)) => match error.code.code() {
+			POOL_INVALID_TX_ERROR => error.data == Some(serde_json::Value::String("Stale".into())),
+			POOL_TOO_LOW_PRIORITY_ERROR => true,
+			_ => false,
+		},
+		_ => false,
+	}
+}
+
+/// Check that runtime provides all required APIs of supported versions.
+fn check_runtime_apis(runtime_version: &sp_version::RuntimeVersion) {
+	for (api_name, supported_version) in REQUIRED_RUNTIME_APIS {
+		let api_id = sp_core::hashing::blake2_64(api_name.as_bytes());
+		let api_version = runtime_version.apis.iter()
+			.find(|(id, _)| *id == api_id)
+			.map(|(_, version)| *version);
+		match api_version {
+			Some(api_version) if api_version == *supported_version => (),
+			Some(api_version) => error!(
+				target: "secretstore",
+				"Runtime (spec_version {}) provides unsupported {} version {}. Supported version: {}. \
+					Key server may malfunction until it is upgraded",
+				runtime_version.spec_version,
+				api_name,
+				api_version,
+				supported_version,
+			),
+			None => error!(
+				target: "secretstore",
+				"Runtime (spec_version {}) does not provide {}. Key server may malfunction until it is upgraded",
+				runtime_version.spec_version,
+				api_name,
+			),
+		}
+	}
 }
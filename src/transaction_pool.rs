@@ -1,22 +1,32 @@
 use parity_secretstore_substrate_service::{
 	TransactionPool, SecretStoreCall,
 };
+use std::sync::Arc;
+use log::warn;
 use crate::{
-	runtime::{TransactionHash},
-	substrate_client::Client,
+	async_bridge::AsyncBridge,
+	checkpoint::{ProcessedBlocks, ServiceRequest},
+	runtime::{self, DefaultRuntime, SecretStoreRuntime, TransactionHash},
+	substrate_client::{Client, TransactionOutcome},
 };
 
-///
+/// Transaction pool that submits SecretStore service transactions to the Substrate node.
 pub struct SecretStoreTransactionPool {
 	/// Substrate node RPC client.
 	client: Client,
+	/// Bridge that is used to call async client methods.
+	bridge: AsyncBridge,
+	/// Blocks and requests that are processed by the service.
+	processed_blocks: Arc<ProcessedBlocks>,
 }
 
 impl SecretStoreTransactionPool {
-	////
-	pub fn new(client: Client) -> SecretStoreTransactionPool {
+	/// Create new transaction pool.
+	pub fn new(client: Client, bridge: AsyncBridge, processed_blocks: Arc<ProcessedBlocks>) -> SecretStoreTransactionPool {
 		SecretStoreTransactionPool {
 			client,
+			bridge,
+			processed_blocks,
 		}
 	}
 }
@@ -24,7 +34,74 @@ impl SecretStoreTransactionPool {
 impl TransactionPool for SecretStoreTransactionPool {
 	type TransactionHash = TransactionHash;
 
-	fn submit_transaction(&self, _call: SecretStoreCall) -> Result<Self::TransactionHash, String> {
-		unimplemented!()
+	fn submit_transaction(&self, call: SecretStoreCall) -> Result<Self::TransactionHash, String> {
+		let completed_request = completed_request(&call);
+		let call = DefaultRuntime::secret_store_call(into_runtime_call(call));
+		let client = self.client.clone();
+		let (transaction_hash, outcome) = self.bridge.run(async move {
+			client.submit_transaction(call).await
+		}).map_err(|error| format!("{:?}", error))?;
+
+		if let Some(completed_request) = completed_request {
+			self.processed_blocks.on_request_completed(completed_request);
+		}
+
+		// keep watching transaction, so that it is resubmitted if it expires
+		self.bridge.spawn(async move {
+			match outcome.await {
+				Ok(TransactionOutcome::Finalized { dispatch_result: Ok(()), .. }) => (),
+				outcome => warn!(
+					target: "secretstore",
+					"Service transaction {} has not been dispatched: {:?}",
+					transaction_hash,
+					outcome,
+				),
+			}
+		});
+
+		Ok(transaction_hash)
+	}
+}
+
+/// Get service request that is completed by the service call.
+fn completed_request(call: &SecretStoreCall) -> Option<ServiceRequest> {
+	match *call {
+		SecretStoreCall::ServerKeyGenerated(key_id, _) | SecretStoreCall::ServerKeyGenerationError(key_id) =>
+			Some(ServiceRequest::ServerKeyGeneration(key_id)),
+		SecretStoreCall::ServerKeyRetrieved(key_id, _, _) | SecretStoreCall::ServerKeyRetrievalError(key_id) =>
+			Some(ServiceRequest::ServerKeyRetrieval(key_id)),
+		SecretStoreCall::DocumentKeyStored(key_id) | SecretStoreCall::DocumentKeyStoreError(key_id) =>
+			Some(ServiceRequest::DocumentKeyStore(key_id)),
+		_ => None,
+	}
+}
+
+/// Convert service call into runtime call.
+fn into_runtime_call(call: SecretStoreCall) -> runtime::SecretStoreCall {
+	match call {
+		SecretStoreCall::ServerKeyGenerated(key_id, key) =>
+			runtime::SecretStoreCall::server_key_generated(key_id, key),
+		SecretStoreCall::ServerKeyGenerationError(key_id) =>
+			runtime::SecretStoreCall::server_key_generation_error(key_id),
+		SecretStoreCall::ServerKeyRetrieved(key_id, key, threshold) =>
+			runtime::SecretStoreCall::server_key_retrieved(key_id, key, threshold),
+		SecretStoreCall::ServerKeyRetrievalError(key_id) =>
+			runtime::SecretStoreCall::server_key_retrieval_error(key_id),
+		SecretStoreCall::DocumentKeyStored(key_id) =>
+			runtime::SecretStoreCall::document_key_stored(key_id),
+		SecretStoreCall::DocumentKeyStoreError(key_id) =>
+			runtime::SecretStoreCall::document_key_store_error(key_id),
+		SecretStoreCall::DocumentKeyCommonRetrieved(key_id, requester, common_point, threshold) =>
+			runtime::SecretStoreCall::document_key_common_retrieved(key_id, requester, common_point, threshold),
+		SecretStoreCall::DocumentKeyPersonalRetrieved(key_id, requester, participants, decrypted_secret, shadow) =>
+			runtime::SecretStoreCall::document_key_personal_retrieved(
+				key_id,
+				requester,
+				participants,
+				decrypted_secret,
+				shadow,
+			),
+		SecretStoreCall::DocumentKeyShadowRetrievalError(key_id, requester) =>
+			runtime::SecretStoreCall::document_key_shadow_retrieval_error(key_id, requester),
 	}
 }